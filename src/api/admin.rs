@@ -1,14 +1,12 @@
 //! # Admin API Category
 
-use reqwest::StatusCode;
-
 use crate::{
     client::MarzbanAPIClient,
+    endpoint::{Endpoint, Json, Text},
     error::ApiError,
     models::{
         admin::{Admin, AdminCreate, AdminModify},
         auth::BodyAdminTokenApiAdminTokenPost,
-        errors::HTTPValidationError,
         token::Token,
     },
 };
@@ -27,27 +25,12 @@ impl MarzbanAPIClient {
         &self,
         auth: BodyAdminTokenApiAdminTokenPost,
     ) -> Result<Token, ApiError> {
-        let url = format!("{}/api/admin/token", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
-            .await
-            .form(&auth)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Token>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::post("/api/admin/token")
+                .form(&auth)?
+                .without_reauthentication(),
+        )
+        .await
     }
 
     /// `POST /api/admin/token`
@@ -59,6 +42,9 @@ impl MarzbanAPIClient {
     /// This method takes in a BodyAdminTokenApiAdminTokenPost, and if auth is successful, stores the returned token into the MarzbanAPIClient struct.
     ///
     /// If you want to retrieve the token without storing it in the client, use [MarzbanAPIClient::admin_token()] instead.
+    ///
+    /// The credentials are not remembered, use [MarzbanAPIClient::set_credentials()] to
+    /// automatically re-authenticate once the token expires.
     pub async fn authenticate(
         &self,
         auth: BodyAdminTokenApiAdminTokenPost,
@@ -73,65 +59,15 @@ impl MarzbanAPIClient {
     ///
     /// Retrieve the current authenticated admin.
     pub async fn get_current_admin(&self) -> Result<Admin, ApiError> {
-        let url = format!("{}/api/admin", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Admin>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNAUTHORIZED => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(Endpoint::<Json<_>>::get("/api/admin")).await
     }
 
     /// `POST /api/admin`
     ///
     /// Create a new admin if the current admin has sudo privileges.
     pub async fn create_admin(&self, body: AdminCreate) -> Result<Admin, ApiError> {
-        let url = format!("{}/api/admin", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
+        self.execute(Endpoint::<Json<_>>::post("/api/admin").json(&body)?)
             .await
-            .json(&body)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Admin>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNAUTHORIZED => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::CONFLICT => Err(ApiError::ApiResponseError(
-                "Admin already exists".to_string(),
-            )),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `PUT /api/admin/{admin_username}`
@@ -142,81 +78,22 @@ impl MarzbanAPIClient {
         admin_username: impl AsRef<str>,
         body: AdminModify,
     ) -> Result<Admin, ApiError> {
-        let url = format!(
-            "{}/api/admin/{}",
-            self.inner.base_url,
-            admin_username.as_ref()
-        );
-        let response = self
-            .prepare_authorized_request(reqwest::Method::PUT, url)
-            .await
-            .json(&body)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Admin>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNAUTHORIZED => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("Admin not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::put(format!("/api/admin/{}", admin_username.as_ref()))
+                .json(&body)?,
+        )
+        .await
     }
 
     /// `DELETE /api/admin/{admin_username}`
     ///
     /// Remove an admin from the database.
-    pub async fn delete_admin(&self, admin_username: impl AsRef<str>) -> Result<Admin, ApiError> {
-        let url = format!(
-            "{}/api/admin/{}",
-            self.inner.base_url,
+    pub async fn delete_admin(&self, admin_username: impl AsRef<str>) -> Result<String, ApiError> {
+        self.execute(Endpoint::<Text>::delete(format!(
+            "/api/admin/{}",
             admin_username.as_ref()
-        );
-        let response = self
-            .prepare_authorized_request(reqwest::Method::DELETE, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Admin>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNAUTHORIZED => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("Admin not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        )))
+        .await
     }
 
     /// `GET /api/admins`
@@ -234,46 +111,12 @@ impl MarzbanAPIClient {
         limit: Option<i32>,
         username: Option<impl Into<String>>,
     ) -> Result<Vec<Admin>, ApiError> {
-        let url = format!("{}/api/admins/", self.inner.base_url);
-        let mut params = Vec::new();
-        if let Some(value) = offset {
-            params.push(("offset", value.to_string()))
-        }
-        if let Some(value) = limit {
-            params.push(("limit", value.to_string()))
-        }
-        if let Some(value) = username {
-            params.push(("username", value.into()))
-        }
-
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .query(&params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Vec<Admin>>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNAUTHORIZED => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::get("/api/admins")
+                .query_opt("offset", offset)
+                .query_opt("limit", limit)
+                .query_opt("username", username.map(Into::<String>::into)),
+        )
+        .await
     }
 }
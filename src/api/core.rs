@@ -1,11 +1,15 @@
 //! # Core API Category
 
-use reqwest::StatusCode;
+use futures::Stream;
+use validator::Validate;
 
 use crate::{
     client::MarzbanAPIClient,
+    config_diff::ConfigDiff,
+    endpoint::{Endpoint, Json, Text},
     error::ApiError,
-    models::{errors::HTTPValidationError, system::CoreStats},
+    logs::{LogLine, LogsStreamOptions},
+    models::{system::CoreStats, xray::XrayConfig},
 };
 
 impl MarzbanAPIClient {
@@ -13,104 +17,113 @@ impl MarzbanAPIClient {
     ///
     /// Retrieve core statistics such as version and uptime.
     pub async fn get_core_stats(&self) -> Result<CoreStats, ApiError> {
-        let url = format!("{}/api/core", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<CoreStats>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(Endpoint::<Json<_>>::get("/api/core")).await
     }
 
     /// `POST /api/core/restart`
     ///
     /// Restart the core and all connected nodes.
     pub async fn restart_core(&self) -> Result<String, ApiError> {
-        let url = format!("{}/api/core", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
+        self.execute(Endpoint::<Text>::post("/api/core/restart"))
             .await
-            .send()
-            .await?;
+    }
 
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+    /// `WS /api/core/logs`
+    ///
+    /// Stream the core logs, authenticated with the stored token.
+    ///
+    /// The connection is re-established when it drops, see [`LogsStreamOptions`].
+    /// Marzban sends its recent log lines on every connection, so lines may be repeated after reconnecting.
+    ///
+    /// ## Note
+    ///
+    /// The websocket does not go through the [`reqwest::Client`], so the proxies and TLS settings
+    /// of the [`MarzbanAPIClientBuilder`](crate::client::MarzbanAPIClientBuilder) are not applied.
+    pub fn core_logs_stream(&self) -> impl Stream<Item = Result<LogLine, ApiError>> {
+        self.core_logs_stream_with_options(LogsStreamOptions::default())
+    }
+
+    /// `WS /api/core/logs`
+    ///
+    /// Same as [`MarzbanAPIClient::core_logs_stream()`], with a configurable interval and reconnection.
+    pub fn core_logs_stream_with_options(
+        &self,
+        options: LogsStreamOptions,
+    ) -> impl Stream<Item = Result<LogLine, ApiError>> {
+        self.logs_stream("/api/core/logs".to_string(), options)
     }
 
     /// `GET /api/core/config`
     ///
     /// Get the current core configuration.
     pub async fn get_core_config(&self) -> Result<String, ApiError> {
-        let url = format!("{}/api/core/config", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
+        self.execute(Endpoint::<Text>::get("/api/core/config"))
             .await
-            .send()
-            .await?;
+    }
 
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+    /// `GET /api/core/config`
+    ///
+    /// Get the current core configuration as a typed [`XrayConfig`].
+    pub async fn get_xray_config(&self) -> Result<XrayConfig, ApiError> {
+        self.execute(Endpoint::<Json<_>>::get("/api/core/config"))
+            .await
     }
 
     /// `PUT /api/core/config`
     ///
     /// Modify the core configuration and restart the core.
+    ///
+    /// The configuration must be a JSON object, otherwise [`ApiError::Serialization`] is returned
+    /// without sending the request.
     pub async fn modify_core_config(
         &self,
         config_as_json: impl AsRef<str>,
     ) -> Result<String, ApiError> {
-        let url = format!("{}/api/core/config", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::PUT, url)
+        let config: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(config_as_json.as_ref())?;
+        self.execute(Endpoint::<Text>::put("/api/core/config").json(&config)?)
             .await
-            .json(config_as_json.as_ref())
-            .send()
-            .await?;
+    }
 
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
+    /// `PUT /api/core/config`
+    ///
+    /// Modify the core configuration with a typed [`XrayConfig`] and restart the core.
+    ///
+    /// The configuration is validated before sending, see [`XrayConfig::validate()`].
+    pub async fn modify_xray_config(&self, config: &XrayConfig) -> Result<XrayConfig, ApiError> {
+        config.validate()?;
+        self.put_xray_config(config).await
+    }
+
+    /// Send an already validated configuration.
+    async fn put_xray_config(&self, config: &XrayConfig) -> Result<XrayConfig, ApiError> {
+        self.execute(Endpoint::<Json<_>>::put("/api/core/config").json(config)?)
+            .await
+    }
+
+    /// `GET /api/core/config`
+    ///
+    /// Compare the current core configuration with a `proposed` one, without modifying anything.
+    pub async fn diff_xray_config(&self, proposed: &XrayConfig) -> Result<ConfigDiff, ApiError> {
+        Ok(self.get_xray_config().await?.diff(proposed))
+    }
+
+    /// `GET /api/core/config`, then `PUT /api/core/config`
+    ///
+    /// Validate `config` and diff it against the current configuration. Unless `dry_run` is set
+    /// or nothing changed, the configuration is then applied, restarting the core and all nodes.
+    ///
+    /// Returns the diff either way.
+    pub async fn apply_xray_config(
+        &self,
+        config: &XrayConfig,
+        dry_run: bool,
+    ) -> Result<ConfigDiff, ApiError> {
+        config.validate()?;
+        let diff = self.diff_xray_config(config).await?;
+        if !dry_run && !diff.is_empty() {
+            self.put_xray_config(config).await?;
         }
+        Ok(diff)
     }
 }
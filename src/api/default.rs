@@ -1,23 +1,16 @@
 //! # Default API Category
 
-use reqwest::StatusCode;
-
-use crate::{client::MarzbanAPIClient, error::ApiError};
+use crate::{
+    client::MarzbanAPIClient,
+    endpoint::{Endpoint, Text},
+    error::ApiError,
+};
 
 impl MarzbanAPIClient {
     /// `GET /`
     ///
     /// Base URL of the Marzban panel.
     pub async fn base_url(&self) -> Result<String, ApiError> {
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, &self.inner.base_url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(Endpoint::<Text>::get("")).await
     }
 }
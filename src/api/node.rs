@@ -1,14 +1,14 @@
 //! # Node API Category
 
-use reqwest::StatusCode;
+use futures::Stream;
+use validator::Validate;
 
 use crate::{
     client::MarzbanAPIClient,
+    endpoint::{Endpoint, Json, Text},
     error::ApiError,
-    models::{
-        errors::HTTPValidationError,
-        node::{NodeCreate, NodeModify, NodeResponse, NodeSettings, NodesUsageResponse},
-    },
+    logs::{LogLine, LogsStreamOptions},
+    models::node::{NodeCreate, NodeModify, NodeResponse, NodeSettings, NodesUsageResponse},
 };
 
 impl MarzbanAPIClient {
@@ -16,83 +16,25 @@ impl MarzbanAPIClient {
     ///
     /// Retrieve the current node settings, including TLS certificate.
     pub async fn get_node_settings(&self) -> Result<NodeSettings, ApiError> {
-        let url = format!("{}/api/node/settings", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
+        self.execute(Endpoint::<Json<_>>::get("/api/node/settings"))
             .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<NodeSettings>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `POST /api/node`
     ///
     /// Add a new node to the database and optionally add it as a host.
     pub async fn add_node(&self, body: NodeCreate) -> Result<NodeResponse, ApiError> {
-        let url = format!("{}/api/node", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
+        body.validate()?;
+        self.execute(Endpoint::<Json<_>>::post("/api/node").json(&body)?)
             .await
-            .json(&body)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<NodeResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `GET /api/node/{node_id}`
     ///
     /// Retrieve details of a specific node by its ID.
     pub async fn get_node(&self, node_id: i32) -> Result<NodeResponse, ApiError> {
-        let url = format!("{}/api/node/{}", self.inner.base_url, node_id);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
+        self.execute(Endpoint::<Json<_>>::get(format!("/api/node/{node_id}")))
             .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<NodeResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("Node not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `PUT /api/node/{node_id}`
@@ -103,108 +45,54 @@ impl MarzbanAPIClient {
         node_id: i32,
         body: NodeModify,
     ) -> Result<NodeResponse, ApiError> {
-        let url = format!("{}/api/node/{}", self.inner.base_url, node_id);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::PUT, url)
+        body.validate()?;
+        self.execute(Endpoint::<Json<_>>::put(format!("/api/node/{node_id}")).json(&body)?)
             .await
-            .json(&body)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<NodeResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("Node not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `DELETE /api/node/{node_id}`
     ///
     /// Delete a node and remove it from xray in the background.
     pub async fn remove_node(&self, node_id: i32) -> Result<String, ApiError> {
-        let url = format!("{}/api/node/{}", self.inner.base_url, node_id);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::DELETE, url)
+        self.execute(Endpoint::<Text>::delete(format!("/api/node/{node_id}")))
             .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("Node not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `GET /api/nodes`
     ///
     /// Retrieve a list of all nodes. Accessible only to sudo admins.
     pub async fn get_nodes(&self) -> Result<Vec<NodeResponse>, ApiError> {
-        let url = format!("{}/api/nodes", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Vec<NodeResponse>>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(Endpoint::<Json<_>>::get("/api/nodes")).await
     }
 
     /// `POST /api/node/{node_id}/reconnect`
     ///
     /// Trigger a reconnection for the specified node. Only accessible to sudo admins.
     pub async fn reconnect_node(&self, node_id: i32) -> Result<String, ApiError> {
-        let url = format!("{}/api/node/{}/reconnect", self.inner.base_url, node_id);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
-            .await
-            .send()
-            .await?;
+        self.execute(Endpoint::<Text>::post(format!(
+            "/api/node/{node_id}/reconnect"
+        )))
+        .await
+    }
 
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("Node not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+    /// `WS /api/node/{node_id}/logs`
+    ///
+    /// Stream the logs of the specified node. Only accessible to sudo admins.
+    ///
+    /// See [`MarzbanAPIClient::core_logs_stream()`] for the reconnection behaviour.
+    pub fn node_logs_stream(&self, node_id: i32) -> impl Stream<Item = Result<LogLine, ApiError>> {
+        self.node_logs_stream_with_options(node_id, LogsStreamOptions::default())
+    }
+
+    /// `WS /api/node/{node_id}/logs`
+    ///
+    /// Same as [`MarzbanAPIClient::node_logs_stream()`], with a configurable interval and reconnection.
+    pub fn node_logs_stream_with_options(
+        &self,
+        node_id: i32,
+        options: LogsStreamOptions,
+    ) -> impl Stream<Item = Result<LogLine, ApiError>> {
+        self.logs_stream(format!("/api/node/{node_id}/logs"), options)
     }
 
     /// `GET /api/nodes/usage`
@@ -220,38 +108,11 @@ impl MarzbanAPIClient {
         start: Option<impl Into<String>>,
         end: Option<impl Into<String>>,
     ) -> Result<NodesUsageResponse, ApiError> {
-        let url = format!("{}/api/nodes/usage", self.inner.base_url);
-        let mut params = Vec::new();
-        if let Some(value) = start {
-            params.push(("start", value.into()))
-        }
-        if let Some(value) = end {
-            params.push(("end", value.into()))
-        }
-
-        let response = self
-            .prepare_authorized_request(reqwest::Method::DELETE, url)
-            .await
-            .query(&params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<NodesUsageResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("Node not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::get("/api/nodes/usage")
+                .query_opt("start", start.map(Into::<String>::into))
+                .query_opt("end", end.map(Into::<String>::into)),
+        )
+        .await
     }
 }
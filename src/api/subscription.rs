@@ -1,12 +1,14 @@
 //! # Subscription API Category
 
-use reqwest::StatusCode;
+use reqwest::header::{HeaderValue, ACCEPT, USER_AGENT};
+use validator::{ValidationError, ValidationErrors};
 
 use crate::{
     client::MarzbanAPIClient,
+    endpoint::{Endpoint, Json, Raw},
     error::ApiError,
     models::{
-        errors::HTTPValidationError,
+        subscription::{Subscription, SubscriptionResponse},
         user::{UserResponse, UserUsagesResponse},
     },
 };
@@ -22,24 +24,41 @@ impl MarzbanAPIClient {
     ///
     /// For example, if the user agent is Clash, the response will be a Clash subscription link.
     /// If the user agent is a browser, the response will be a web page.
-    pub async fn user_subscription(&self, user_token: impl AsRef<str>) -> Result<String, ApiError> {
-        let url = format!("{}/sub/{}", self.inner.base_url, user_token.as_ref());
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
+    ///
+    /// The `subscription-userinfo` and profile headers are returned alongside the body.
+    pub async fn user_subscription(
+        &self,
+        user_token: impl AsRef<str>,
+    ) -> Result<SubscriptionResponse, ApiError> {
+        self.subscription(Endpoint::get(format!("/sub/{}", user_token.as_ref())))
             .await
-            .send()
-            .await?;
+    }
 
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
+    /// `GET /sub/{user_token}`
+    ///
+    /// Same as [`MarzbanAPIClient::user_subscription()`], as seen by the given client app.
+    ///
+    /// [`SubscriptionUserAgent::Browser`] also sends `Accept: text/html`, for which Marzban returns its subscription page.
+    pub async fn user_subscription_with_user_agent(
+        &self,
+        user_token: impl AsRef<str>,
+        user_agent: SubscriptionUserAgent,
+    ) -> Result<SubscriptionResponse, ApiError> {
+        let value = HeaderValue::from_str(user_agent.as_str()).map_err(|_| {
+            let mut errors = ValidationErrors::new();
+            errors.add(
+                "user_agent",
+                ValidationError::new("header")
+                    .with_message("User agent must be a valid header value".into()),
+            );
+            errors
+        })?;
+        let mut endpoint =
+            Endpoint::<Raw>::get(format!("/sub/{}", user_token.as_ref())).header(USER_AGENT, value);
+        if user_agent == SubscriptionUserAgent::Browser {
+            endpoint = endpoint.header(ACCEPT, HeaderValue::from_static(BROWSER_ACCEPT));
         }
+        self.subscription(endpoint).await
     }
 
     /// `GET /sub/{user_token}/info`
@@ -49,26 +68,11 @@ impl MarzbanAPIClient {
         &self,
         user_token: impl AsRef<str>,
     ) -> Result<UserResponse, ApiError> {
-        let url = format!("{}/sub/{}/info", self.inner.base_url, user_token.as_ref());
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(Endpoint::<Json<_>>::get(format!(
+            "/sub/{}/info",
+            user_token.as_ref()
+        )))
+        .await
     }
 
     /// `GET /sub/{user_token}/usage`
@@ -85,70 +89,71 @@ impl MarzbanAPIClient {
         start: Option<impl Into<String>>,
         end: Option<impl Into<String>>,
     ) -> Result<UserUsagesResponse, ApiError> {
-        let url = format!("{}/sub/{}/usage", self.inner.base_url, user_token.as_ref());
-        let mut params = Vec::new();
-        if let Some(value) = start {
-            params.push(("start", value.into()))
-        }
-        if let Some(value) = end {
-            params.push(("end", value.into()))
-        }
-
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .query(&params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserUsagesResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::get(format!("/sub/{}/usage", user_token.as_ref()))
+                .query_opt("start", start.map(Into::<String>::into))
+                .query_opt("end", end.map(Into::<String>::into)),
+        )
+        .await
     }
 
     /// `GET /sub/{user_token}/{client_type}`
     ///
     /// Provides a subscription link based on the specified client type (e.g., Clash, V2Ray).
+    ///
+    /// The `subscription-userinfo` and profile headers are returned alongside the body.
     pub async fn user_subscription_with_client_type(
         &self,
         user_token: impl AsRef<str>,
         client_type: ClientTypes,
-    ) -> Result<String, ApiError> {
-        let url = format!(
-            "{}/sub/{}/{}",
-            self.inner.base_url,
+    ) -> Result<SubscriptionResponse, ApiError> {
+        self.subscription(Endpoint::get(format!(
+            "/sub/{}/{}",
             user_token.as_ref(),
             client_type
-        );
+        )))
+        .await
+    }
+
+    /// `GET /sub/{user_token}/{client_type}`
+    ///
+    /// Same as [`MarzbanAPIClient::user_subscription_with_client_type()`], decoded into a typed [`Subscription`].
+    pub async fn user_subscription_decoded(
+        &self,
+        user_token: impl AsRef<str>,
+        client_type: ClientTypes,
+    ) -> Result<Subscription, ApiError> {
         let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .send()
+            .user_subscription_with_client_type(user_token, client_type)
             .await?;
+        Ok(response.decode(client_type)?)
+    }
 
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+    /// The absolute subscription URL of `user`, optionally for a client type.
+    ///
+    /// Relative subscription URLs are resolved against the base URL of the client.
+    pub fn user_subscription_url(
+        &self,
+        user: &UserResponse,
+        client_type: Option<ClientTypes>,
+    ) -> Option<String> {
+        user.subscription_url_for(&self.inner.base_url, client_type)
+    }
+
+    async fn subscription(
+        &self,
+        endpoint: Endpoint<Raw>,
+    ) -> Result<SubscriptionResponse, ApiError> {
+        let response = self.execute(endpoint).await?;
+        let headers = response.headers().clone();
+        Ok(SubscriptionResponse::from_parts(
+            &headers,
+            response.text().await?,
+        ))
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ClientTypes {
     SingBox,
     ClashMeta,
@@ -171,6 +176,48 @@ impl std::fmt::Display for ClientTypes {
     }
 }
 
+const BROWSER_ACCEPT: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+
+/// The user agent of a client app, which Marzban uses to pick the subscription format of `/sub/{token}`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SubscriptionUserAgent {
+    Clash,
+    ClashMeta,
+    SingBox,
+    V2rayN,
+    V2rayNG,
+    Streisand,
+    Hiddify,
+    /// A desktop browser, which gets the subscription page.
+    Browser,
+    Custom(String),
+}
+
+impl SubscriptionUserAgent {
+    /// The `User-Agent` header sent for this client.
+    pub fn as_str(&self) -> &str {
+        match self {
+            SubscriptionUserAgent::Clash => "ClashForWindows/0.20.39",
+            SubscriptionUserAgent::ClashMeta => "clash.meta/v1.18.10",
+            SubscriptionUserAgent::SingBox => "SFA/1.10.1 (android; sing-box 1.10.1)",
+            SubscriptionUserAgent::V2rayN => "v2rayN/7.2.3",
+            SubscriptionUserAgent::V2rayNG => "v2rayNG/1.9.16",
+            SubscriptionUserAgent::Streisand => "Streisand/1.6.42",
+            SubscriptionUserAgent::Hiddify => "HiddifyNext/2.5.7",
+            SubscriptionUserAgent::Browser => {
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36"
+            }
+            SubscriptionUserAgent::Custom(user_agent) => user_agent,
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionUserAgent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2,13 +2,11 @@
 
 use std::collections::HashMap;
 
-use reqwest::StatusCode;
-
 use crate::{
     client::MarzbanAPIClient,
+    endpoint::{Endpoint, Json},
     error::ApiError,
     models::{
-        errors::HTTPValidationError,
         proxy::{ProxyHost, ProxyInbound, ProxyTypes},
         system::SystemStats,
     },
@@ -19,78 +17,22 @@ impl MarzbanAPIClient {
     ///
     /// Fetch system stats including memory, CPU, and user metrics.
     pub async fn get_system_stats(&self) -> Result<SystemStats, ApiError> {
-        let url = format!("{}/api/system", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<SystemStats>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(Endpoint::<Json<_>>::get("/api/system")).await
     }
 
     /// `GET /api/inbounds`
     ///
     /// Retrieve inbound configurations grouped by protocol.
     pub async fn get_inbounds(&self) -> Result<HashMap<ProxyTypes, Vec<ProxyInbound>>, ApiError> {
-        let url = format!("{}/api/inbounds", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
+        self.execute(Endpoint::<Json<_>>::get("/api/inbounds"))
             .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<HashMap<ProxyTypes, Vec<ProxyInbound>>>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
-    /// `PUT /api/inbounds`
+    /// `GET /api/hosts`
     ///
     /// Get a list of proxy hosts grouped by inbound tag.
-    pub async fn get_hosts(&self) -> Result<HashMap<ProxyTypes, Vec<ProxyHost>>, ApiError> {
-        let url = format!("{}/api/hosts", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<HashMap<ProxyTypes, Vec<ProxyHost>>>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+    pub async fn get_hosts(&self) -> Result<HashMap<String, Vec<ProxyHost>>, ApiError> {
+        self.execute(Endpoint::<Json<_>>::get("/api/hosts")).await
     }
 
     /// `PUT /api/hosts`
@@ -100,29 +42,7 @@ impl MarzbanAPIClient {
         &self,
         body: impl Into<HashMap<String, Vec<ProxyHost>>>,
     ) -> Result<HashMap<String, Vec<ProxyHost>>, ApiError> {
-        let url = format!("{}/api/hosts", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::PUT, url)
+        self.execute(Endpoint::<Json<_>>::put("/api/hosts").json(&body.into())?)
             .await
-            .json(&body.into())
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<HashMap<String, Vec<ProxyHost>>>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 }
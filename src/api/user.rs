@@ -1,29 +1,197 @@
 //! # User API Category
 
 use chrono::{DateTime, Utc};
-use reqwest::StatusCode;
-use serde::Serialize;
+use futures::{stream, Stream, TryStreamExt};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use tokio::task::JoinHandle;
+use validator::Validate;
 
 use crate::{
+    bulk::{run_bulk, BulkOptions, BulkReport},
     client::MarzbanAPIClient,
+    endpoint::{Endpoint, Json, Text},
     error::ApiError,
-    models::{
-        errors::HTTPValidationError,
-        user::{
-            UserCreate, UserModify, UserResponse, UserStatus, UserUsagesResponse, UsersResponse,
-            UsersUsagesResponse,
-        },
+    models::user::{
+        UserCreate, UserModify, UserResponse, UserStatus, UserUsagesResponse, UsersResponse,
+        UsersUsagesResponse,
     },
 };
 
 // Custom struct for query params in get users
-#[derive(Serialize)]
+//
+// Serializes to the same query pairs as sent by `get_users`, so it can be passed to
+// `reqwest::RequestBuilder::query` directly.
+#[derive(Clone, Default, Debug)]
 pub struct GetUsersQueryParams {
     pub offset: Option<i32>,
     pub limit: Option<i32>,
+    /// Only return users with exactly these usernames.
     pub username: Option<Vec<String>>,
+    /// Only return users whose username or note contains this text.
+    pub search: Option<String>,
+    /// Only return users owned by these admins.
+    pub admin: Option<Vec<String>>,
     pub status: Option<UserStatus>,
-    pub sort: Option<String>,
+    /// Sort order, applied in the given order of precedence.
+    pub sort: Option<Vec<UserSort>>,
+}
+
+impl GetUsersQueryParams {
+    /// The query pairs as Marzban expects them, with list parameters repeated
+    /// (`username=a&username=b`) and the sort options comma separated (`sort=-expire,username`).
+    pub(crate) fn to_query_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        if let Some(value) = self.offset {
+            params.push(("offset", value.to_string()))
+        }
+        if let Some(value) = self.limit {
+            params.push(("limit", value.to_string()))
+        }
+        for value in self.username.iter().flatten() {
+            params.push(("username", value.clone()))
+        }
+        if let Some(value) = &self.search {
+            params.push(("search", value.clone()))
+        }
+        for value in self.admin.iter().flatten() {
+            params.push(("admin", value.clone()))
+        }
+        if let Some(value) = self.status {
+            params.push(("status", value.to_string()))
+        }
+        if let Some(value) = self.sort.as_ref().filter(|sort| !sort.is_empty()) {
+            params.push((
+                "sort",
+                value
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ))
+        }
+        params
+    }
+}
+
+impl Serialize for GetUsersQueryParams {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let pairs = self.to_query_pairs();
+        let mut map = serializer.serialize_map(Some(pairs.len()))?;
+        for (key, value) in &pairs {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// A sort option for [`MarzbanAPIClient::get_users()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserSort {
+    Asc(UserSortField),
+    Desc(UserSortField),
+}
+
+/// The fields users can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserSortField {
+    Username,
+    CreatedAt,
+    UsedTraffic,
+    DataLimit,
+    Expire,
+}
+
+impl std::fmt::Display for UserSortField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserSortField::Username => write!(f, "username"),
+            UserSortField::CreatedAt => write!(f, "created_at"),
+            UserSortField::UsedTraffic => write!(f, "used_traffic"),
+            UserSortField::DataLimit => write!(f, "data_limit"),
+            UserSortField::Expire => write!(f, "expire"),
+        }
+    }
+}
+
+impl std::fmt::Display for UserSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserSort::Asc(field) => write!(f, "{field}"),
+            UserSort::Desc(field) => write!(f, "-{field}"),
+        }
+    }
+}
+
+/// Options for [`MarzbanAPIClient::users_stream_with_options()`].
+#[derive(Debug, Clone)]
+pub struct UsersStreamOptions {
+    /// Number of users requested per page. Defaults to `100`.
+    pub page_size: i32,
+    /// Request the next page in the background while the current one is being consumed.
+    /// Defaults to `false`.
+    pub prefetch: bool,
+}
+
+impl Default for UsersStreamOptions {
+    fn default() -> Self {
+        UsersStreamOptions {
+            page_size: 100,
+            prefetch: false,
+        }
+    }
+}
+
+/// Aborts the prefetch task if the stream is dropped before the page was consumed.
+struct PrefetchedPage(JoinHandle<Result<UsersResponse, ApiError>>);
+
+impl Drop for PrefetchedPage {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+struct UsersStreamState {
+    client: MarzbanAPIClient,
+    filter: GetUsersQueryParams,
+    options: UsersStreamOptions,
+    offset: i32,
+    finished: bool,
+    prefetched: Option<PrefetchedPage>,
+}
+
+impl UsersStreamState {
+    fn fetch(
+        &self,
+        offset: i32,
+    ) -> impl std::future::Future<Output = Result<UsersResponse, ApiError>> {
+        let client = self.client.clone();
+        let filter = GetUsersQueryParams {
+            offset: Some(offset),
+            limit: Some(self.options.page_size),
+            ..self.filter.clone()
+        };
+        async move { client.get_users(filter).await }
+    }
+
+    async fn next_page(mut self) -> Result<Option<(Vec<UserResponse>, Self)>, ApiError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let page = match self.prefetched.take() {
+            Some(mut prefetched) => match (&mut prefetched.0).await {
+                Ok(page) => page?,
+                Err(_) => self.fetch(self.offset).await?,
+            },
+            None => self.fetch(self.offset).await?,
+        };
+
+        self.offset += page.users.len() as i32;
+        self.finished = page.users.is_empty() || self.offset as u64 >= page.total;
+        if self.options.prefetch && !self.finished {
+            self.prefetched = Some(PrefetchedPage(tokio::spawn(self.fetch(self.offset))));
+        }
+        Ok(Some((page.users, self)))
+    }
 }
 
 impl MarzbanAPIClient {
@@ -33,69 +201,31 @@ impl MarzbanAPIClient {
     ///
     /// - **username**: 3 to 32 characters, can include a-z, 0-9, and underscores.
     /// - **status**: User's status, defaults to `active``. Special rules if `on_hold``.
-    /// - **expire**: UTC timestamp for account expiration. Use `0` for unlimited.
-    /// - **data_limit**: Max data usage in bytes (e.g., `1073741824` for 1GB). `0`` means unlimited.
+    /// - **expire**: Account expiration, see [`Expiry`](crate::models::units::Expiry). Use `Expiry::Never` for unlimited.
+    /// - **data_limit**: Max data usage, see [`ByteSize`](crate::models::units::ByteSize) (e.g., `ByteSize::gib(1)`). `0` means unlimited.
     /// - **data_limit_reset_strategy**: Defines how/if data limit resets. `no_reset` means it never resets.
     /// - **proxies**: Dictionary of protocol settings (e.g., `vmess`, `vless`).
     /// - **inbounds**: Dictionary of protocol tags to specify inbound connections.
     /// - **note**: Optional text field for additional user information or notes.
     /// - **on_hold_timeout**: UTC timestamp when `on_hold` status should start or end.
     /// - **on_hold_expire_duration**: Duration (in seconds) for how long the user should stay in `on_hold` status.
+    ///
+    /// The user is validated before being sent, failing with [`ApiError::ClientValidation`] if invalid.
     pub async fn add_user(&self, new_user: UserCreate) -> Result<UserResponse, ApiError> {
-        let url = format!("{}/api/user", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
+        new_user.validate()?;
+        self.execute(Endpoint::<Json<_>>::post("/api/user").json(&new_user)?)
             .await
-            .json(&new_user)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::CONFLICT => Err(ApiError::ApiResponseError(
-                "User already exists".to_string(),
-            )),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `GET /api/user/{username}`
     ///
     /// Get user information
     pub async fn get_user(&self, username: impl Into<String>) -> Result<UserResponse, ApiError> {
-        let url = format!("{}/api/user/{}", self.inner.base_url, username.into());
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("User not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(Endpoint::<Json<_>>::get(format!(
+            "/api/user/{}",
+            username.into()
+        )))
+        .await
     }
 
     /// `PUT /api/user/{username}`
@@ -104,8 +234,8 @@ impl MarzbanAPIClient {
     ///
     /// - **username**: Cannot be changed. Used to identify the user.
     /// - **status**: User's new status. Can be 'active', 'disabled', 'on_hold', 'limited', or 'expired'.
-    /// - **expire**: UTC timestamp for new account expiration. Set to `0`` for unlimited, `null` for no change.
-    /// - **data_limit**: New max data usage in bytes (e.g., `1073741824` for 1GB). Set to `0` for unlimited, `null` for no change.
+    /// - **expire**: New account expiration. Set to `Expiry::Never` for unlimited, [`None`] for no change.
+    /// - **data_limit**: New max data usage (e.g., `ByteSize::gib(1)`). Set to `0` for unlimited, [`None`] for no change.
     /// - **data_limit_reset_strategy**: New strategy for data limit reset. Options include 'daily', 'weekly', 'monthly', or 'no_reset'.
     /// - **proxies**: Dictionary of new protocol settings (e.g., `vmess`, `vless`). Empty dictionary means no change.
     /// - **inbounds**: Dictionary of new protocol tags to specify inbound connections. Empty dictionary means no change.
@@ -119,58 +249,106 @@ impl MarzbanAPIClient {
         username: impl AsRef<str>,
         body: UserModify,
     ) -> Result<UserResponse, ApiError> {
-        let url = format!("{}/api/user/{}", self.inner.base_url, username.as_ref());
-        let response = self
-            .prepare_authorized_request(reqwest::Method::PUT, url)
-            .await
-            .json(&body)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("User not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        body.validate()?;
+        self.execute(
+            Endpoint::<Json<_>>::put(format!("/api/user/{}", username.as_ref())).json(&body)?,
+        )
+        .await
     }
 
     /// `DELETE /api/user/{username}`
     ///
     /// Remove a user
     pub async fn delete_user(&self, username: impl AsRef<str>) -> Result<String, ApiError> {
-        let url = format!("{}/api/user/{}", self.inner.base_url, username.as_ref());
-        let response = self
-            .prepare_authorized_request(reqwest::Method::DELETE, url)
+        self.execute(Endpoint::<Text>::delete(format!(
+            "/api/user/{}",
+            username.as_ref()
+        )))
+        .await
+    }
+
+    /// `POST /api/user` (bulk)
+    ///
+    /// Add many users concurrently, reporting the outcome for each of them.
+    /// See [`MarzbanAPIClient::bulk_add_users_with_options()`] to configure the concurrency and stop on errors.
+    pub async fn bulk_add_users(
+        &self,
+        users: impl IntoIterator<Item = UserCreate>,
+    ) -> BulkReport<UserResponse> {
+        self.bulk_add_users_with_options(users, BulkOptions::default())
             .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("User not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+    }
+
+    /// `POST /api/user` (bulk)
+    ///
+    /// Same as [`MarzbanAPIClient::bulk_add_users()`], with a configurable concurrency and stop on errors.
+    pub async fn bulk_add_users_with_options(
+        &self,
+        users: impl IntoIterator<Item = UserCreate>,
+        options: BulkOptions,
+    ) -> BulkReport<UserResponse> {
+        let users = users
+            .into_iter()
+            .map(|user| (user.username.clone(), user))
+            .collect();
+        run_bulk(users, options, |_, user| self.add_user(user)).await
+    }
+
+    /// `PUT /api/user/{username}` (bulk)
+    ///
+    /// Modify many users concurrently, reporting the outcome for each of them.
+    /// See [`MarzbanAPIClient::bulk_modify_users_with_options()`] to configure the concurrency and stop on errors.
+    pub async fn bulk_modify_users(
+        &self,
+        users: impl IntoIterator<Item = (String, UserModify)>,
+    ) -> BulkReport<UserResponse> {
+        self.bulk_modify_users_with_options(users, BulkOptions::default())
+            .await
+    }
+
+    /// `PUT /api/user/{username}` (bulk)
+    ///
+    /// Same as [`MarzbanAPIClient::bulk_modify_users()`], with a configurable concurrency and stop on errors.
+    pub async fn bulk_modify_users_with_options(
+        &self,
+        users: impl IntoIterator<Item = (String, UserModify)>,
+        options: BulkOptions,
+    ) -> BulkReport<UserResponse> {
+        let users = users.into_iter().collect();
+        run_bulk(users, options, |username, body| async move {
+            self.modify_user(username, body).await
+        })
+        .await
+    }
+
+    /// `DELETE /api/user/{username}` (bulk)
+    ///
+    /// Delete many users concurrently, reporting the outcome for each of them.
+    /// See [`MarzbanAPIClient::bulk_delete_users_with_options()`] to configure the concurrency and stop on errors.
+    pub async fn bulk_delete_users(
+        &self,
+        usernames: impl IntoIterator<Item = impl Into<String>>,
+    ) -> BulkReport<String> {
+        self.bulk_delete_users_with_options(usernames, BulkOptions::default())
+            .await
+    }
+
+    /// `DELETE /api/user/{username}` (bulk)
+    ///
+    /// Same as [`MarzbanAPIClient::bulk_delete_users()`], with a configurable concurrency and stop on errors.
+    pub async fn bulk_delete_users_with_options(
+        &self,
+        usernames: impl IntoIterator<Item = impl Into<String>>,
+        options: BulkOptions,
+    ) -> BulkReport<String> {
+        let usernames = usernames
+            .into_iter()
+            .map(|username| (username.into(), ()))
+            .collect();
+        run_bulk(usernames, options, |username, ()| async move {
+            self.delete_user(username).await
+        })
+        .await
     }
 
     /// `POST /api/user/{username}/reset`
@@ -180,74 +358,25 @@ impl MarzbanAPIClient {
         &self,
         username: impl AsRef<str>,
     ) -> Result<UserResponse, ApiError> {
-        let url = format!(
-            "{}/api/user/{}/reset",
-            self.inner.base_url,
+        self.execute(Endpoint::<Json<_>>::post(format!(
+            "/api/user/{}/reset",
             username.as_ref()
-        );
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("User not found".to_string())),
-            StatusCode::CONFLICT => Err(ApiError::ApiResponseError(
-                "User already exists".to_string(),
-            )),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        )))
+        .await
     }
 
-    /// `POST /api/user/{username}/renew`
+    /// `POST /api/user/{username}/revoke_sub`
     ///
     /// Revoke users subscription (Subscription link and proxies)
     pub async fn revoke_user_subscription(
         &self,
         username: impl AsRef<str>,
     ) -> Result<UserResponse, ApiError> {
-        let url = format!(
-            "{}/api/user/{}/revoke_sub",
-            self.inner.base_url,
+        self.execute(Endpoint::<Json<_>>::post(format!(
+            "/api/user/{}/revoke_sub",
             username.as_ref()
-        );
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
-            .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("User not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        )))
+        .await
     }
 
     /// `GET /api/users`
@@ -257,47 +386,57 @@ impl MarzbanAPIClient {
         &self,
         query_params: GetUsersQueryParams,
     ) -> Result<UsersResponse, ApiError> {
-        let url = format!("{}/api/users", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .query(&query_params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UsersResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::get("/api/users").query_pairs(query_params.to_query_pairs()),
+        )
+        .await
+    }
+
+    /// `GET /api/users` (paginated)
+    ///
+    /// Lazily stream all users matching the filter, walking the pages using the `total` returned by the API.
+    ///
+    /// The `offset` of the filter is used as the starting offset, while `limit` is replaced by the page size.
+    /// See [`MarzbanAPIClient::users_stream_with_options()`] to configure the page size and prefetching.
+    ///
+    /// ## Note
+    ///
+    /// Users added or removed while the stream is being consumed may shift the pages,
+    /// causing users to be skipped or yielded twice.
+    pub fn users_stream(
+        &self,
+        filter: GetUsersQueryParams,
+    ) -> impl Stream<Item = Result<UserResponse, ApiError>> {
+        self.users_stream_with_options(filter, UsersStreamOptions::default())
+    }
+
+    /// `GET /api/users` (paginated)
+    ///
+    /// Same as [`MarzbanAPIClient::users_stream()`], with a configurable page size and prefetching.
+    pub fn users_stream_with_options(
+        &self,
+        filter: GetUsersQueryParams,
+        options: UsersStreamOptions,
+    ) -> impl Stream<Item = Result<UserResponse, ApiError>> {
+        let state = UsersStreamState {
+            client: self.clone(),
+            offset: filter.offset.unwrap_or(0),
+            filter,
+            options,
+            finished: false,
+            prefetched: None,
+        };
+        stream::try_unfold(state, UsersStreamState::next_page)
+            .map_ok(|users| stream::iter(users.into_iter().map(Ok)))
+            .try_flatten()
     }
 
     /// `POST /api/users/reset`
     ///
     /// Reset all users data usage
     pub async fn reset_all_users_data_usage(&self) -> Result<String, ApiError> {
-        let url = format!("{}/api/users/reset", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
+        self.execute(Endpoint::<Text>::post("/api/users/reset"))
             .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `GET /api/user/{username}/usage`
@@ -309,43 +448,12 @@ impl MarzbanAPIClient {
         start: Option<impl Into<String>>,
         end: Option<impl Into<String>>,
     ) -> Result<UserUsagesResponse, ApiError> {
-        let url = format!(
-            "{}/api/user/{}/usage",
-            self.inner.base_url,
-            username.as_ref()
-        );
-        let mut params = Vec::new();
-        if let Some(value) = start {
-            params.push(("start", value.into()))
-        }
-        if let Some(value) = end {
-            params.push(("end", value.into()))
-        }
-
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .query(&params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserUsagesResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("User not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::get(format!("/api/user/{}/usage", username.as_ref()))
+                .query_opt("start", start.map(Into::<String>::into))
+                .query_opt("end", end.map(Into::<String>::into)),
+        )
+        .await
     }
 
     /// `GET /api/users/usage`
@@ -363,45 +471,18 @@ impl MarzbanAPIClient {
         end: Option<impl Into<String>>,
         admin: Option<Vec<impl Into<String>>>,
     ) -> Result<UsersUsagesResponse, ApiError> {
-        let url = format!("{}/api/users/usage", self.inner.base_url);
-        let mut params = Vec::new();
-        if let Some(value) = start {
-            params.push(("start", value.into()))
-        }
-        if let Some(value) = end {
-            params.push(("end", value.into()))
-        }
-        if let Some(value) = admin {
-            params.push((
-                "admin",
-                value
-                    .into_iter()
-                    .map(|x| {
-                        let x: String = x.into();
-                        x
-                    })
-                    .collect::<Vec<_>>()
-                    .join(","),
-            ))
-        }
-
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .query(&params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UsersUsagesResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        let admins = admin
+            .into_iter()
+            .flatten()
+            .map(|admin| ("admin", admin.into()))
+            .collect();
+        self.execute(
+            Endpoint::<Json<_>>::get("/api/users/usage")
+                .query_opt("start", start.map(Into::<String>::into))
+                .query_opt("end", end.map(Into::<String>::into))
+                .query_pairs(admins),
+        )
+        .await
     }
 
     /// `PUT /api/user/{username}/set-owner`
@@ -416,35 +497,11 @@ impl MarzbanAPIClient {
         username: impl AsRef<str>,
         admin_username: impl Into<String>,
     ) -> Result<UserResponse, ApiError> {
-        let url = format!(
-            "{}/api/user/{}/set-owner",
-            self.inner.base_url,
-            username.as_ref()
-        );
-        let response = self
-            .prepare_authorized_request(reqwest::Method::PUT, url)
-            .await
-            .query(&[("admin_username", admin_username.into())])
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("User not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::put(format!("/api/user/{}/set-owner", username.as_ref()))
+                .query("admin_username", admin_username.into()),
+        )
+        .await
     }
 
     /// `GET /api/users/expired`
@@ -460,35 +517,15 @@ impl MarzbanAPIClient {
         expired_before: Option<DateTime<Utc>>,
         expired_after: Option<DateTime<Utc>>,
     ) -> Result<Vec<String>, ApiError> {
-        let url = format!("{}/api/users/expired", self.inner.base_url);
-        let mut params = Vec::new();
-        if let Some(value) = expired_before {
-            params.push(("expired_before", value))
-        }
-        if let Some(value) = expired_after {
-            params.push(("expired_after", value))
-        }
-
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .query(&params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Vec<String>>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::get("/api/users/expired")
+                .query_opt(
+                    "expired_before",
+                    expired_before.map(|date| date.to_rfc3339()),
+                )
+                .query_opt("expired_after", expired_after.map(|date| date.to_rfc3339())),
+        )
+        .await
     }
 
     /// `DELETE /api/users/expired`
@@ -503,34 +540,77 @@ impl MarzbanAPIClient {
         expired_before: Option<DateTime<Utc>>,
         expired_after: Option<DateTime<Utc>>,
     ) -> Result<Vec<String>, ApiError> {
-        let url = format!("{}/api/users/expired", self.inner.base_url);
-        let mut params = Vec::new();
-        if let Some(value) = expired_before {
-            params.push(("expired_before", value))
-        }
-        if let Some(value) = expired_after {
-            params.push(("expired_after", value))
-        }
+        self.execute(
+            Endpoint::<Json<_>>::delete("/api/users/expired")
+                .query_opt(
+                    "expired_before",
+                    expired_before.map(|date| date.to_rfc3339()),
+                )
+                .query_opt("expired_after", expired_after.map(|date| date.to_rfc3339())),
+        )
+        .await
+    }
+}
 
-        let response = self
-            .prepare_authorized_request(reqwest::Method::DELETE, url)
-            .await
-            .query(&params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Vec<String>>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_string(params: &GetUsersQueryParams) -> String {
+        reqwest::Client::new()
+            .get("http://localhost/api/users")
+            .query(params)
+            .build()
+            .unwrap()
+            .url()
+            .query()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[test]
+    fn empty_query() {
+        assert_eq!(query_string(&GetUsersQueryParams::default()), "");
+    }
+
+    #[test]
+    fn repeated_keys_for_lists() {
+        let params = GetUsersQueryParams {
+            username: Some(vec!["alice".to_string(), "bob".to_string()]),
+            admin: Some(vec!["root".to_string(), "reseller".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            query_string(&params),
+            "username=alice&username=bob&admin=root&admin=reseller"
+        );
+    }
+
+    #[test]
+    fn sort_search_and_pagination() {
+        let params = GetUsersQueryParams {
+            offset: Some(20),
+            limit: Some(10),
+            search: Some("vip user".to_string()),
+            status: Some(UserStatus::OnHold),
+            sort: Some(vec![
+                UserSort::Desc(UserSortField::UsedTraffic),
+                UserSort::Asc(UserSortField::Username),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            query_string(&params),
+            "offset=20&limit=10&search=vip+user&status=on_hold&sort=-used_traffic%2Cusername"
+        );
+    }
+
+    #[test]
+    fn empty_sort_is_omitted() {
+        let params = GetUsersQueryParams {
+            sort: Some(Vec::new()),
+            ..Default::default()
+        };
+        assert_eq!(query_string(&params), "");
     }
 }
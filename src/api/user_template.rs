@@ -1,14 +1,12 @@
 //! # User Template API Category
 
-use reqwest::StatusCode;
+use validator::Validate;
 
 use crate::{
     client::MarzbanAPIClient,
+    endpoint::{Endpoint, Json, Text},
     error::ApiError,
-    models::{
-        errors::HTTPValidationError,
-        user_template::{UserTemplateCreate, UserTemplateModify, UserTemplateResponse},
-    },
+    models::user_template::{UserTemplateCreate, UserTemplateModify, UserTemplateResponse},
 };
 
 impl MarzbanAPIClient {
@@ -25,35 +23,12 @@ impl MarzbanAPIClient {
         offset: Option<i32>,
         limit: Option<i32>,
     ) -> Result<Vec<UserTemplateResponse>, ApiError> {
-        let url = format!("{}/api/user_template", self.inner.base_url);
-        let mut params = Vec::new();
-        if let Some(value) = offset {
-            params.push(("offset", value))
-        }
-        if let Some(value) = limit {
-            params.push(("limit", value))
-        }
-
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
-            .await
-            .query(&params)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<Vec<UserTemplateResponse>>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
+        self.execute(
+            Endpoint::<Json<_>>::get("/api/user_template")
+                .query_opt("offset", offset)
+                .query_opt("limit", limit),
+        )
+        .await
     }
 
     /// `POST /api/user_template`
@@ -68,60 +43,17 @@ impl MarzbanAPIClient {
         &self,
         body: UserTemplateCreate,
     ) -> Result<UserTemplateResponse, ApiError> {
-        let url = format!("{}/api/user_template", self.inner.base_url);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::POST, url)
+        body.validate()?;
+        self.execute(Endpoint::<Json<_>>::post("/api/user_template").json(&body)?)
             .await
-            .json(&body)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserTemplateResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::CONFLICT => Err(ApiError::ApiResponseError(
-                "Template by this name already exists".to_string(),
-            )),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `GET /api/user_template/{id}`
     ///
     /// Get User Template information with id
     pub async fn get_user_template(&self, id: i32) -> Result<UserTemplateResponse, ApiError> {
-        let url = format!("{}/api/user_template/{}", self.inner.base_url, id);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::GET, url)
+        self.execute(Endpoint::<Json<_>>::get(format!("/api/user_template/{id}")))
             .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserTemplateResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError("User not found".to_string())),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `PUT /api/user_template/{id}`
@@ -137,64 +69,16 @@ impl MarzbanAPIClient {
         id: i32,
         body: UserTemplateModify,
     ) -> Result<UserTemplateResponse, ApiError> {
-        let url = format!("{}/api/user_template/{}", self.inner.base_url, id);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::PUT, url)
+        body.validate()?;
+        self.execute(Endpoint::<Json<_>>::put(format!("/api/user_template/{id}")).json(&body)?)
             .await
-            .json(&body)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response
-                .json::<UserTemplateResponse>()
-                .await
-                .map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError(
-                "User Template not found".to_string(),
-            )),
-            StatusCode::CONFLICT => Err(ApiError::ApiResponseError(
-                "Template by this name already exists".to_string(),
-            )),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 
     /// `DELETE /api/user_template/{id}`
     ///
     /// Remove a User Template by its ID
     pub async fn remove_user_template(&self, id: i32) -> Result<String, ApiError> {
-        let url = format!("{}/api/user_template/{}", self.inner.base_url, id);
-        let response = self
-            .prepare_authorized_request(reqwest::Method::DELETE, url)
+        self.execute(Endpoint::<Text>::delete(format!("/api/user_template/{id}")))
             .await
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => response.text().await.map_err(ApiError::NetworkError),
-            StatusCode::FORBIDDEN => {
-                Err(ApiError::ApiResponseError("You're not allowed".to_string()))
-            }
-            StatusCode::NOT_FOUND => Err(ApiError::ApiResponseError(
-                "User Template not found".to_string(),
-            )),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let error_response = response.json::<HTTPValidationError>().await?;
-                Err(ApiError::ApiResponseError(format!(
-                    "Validation Error: {error_response:?}"
-                )))
-            }
-            _ => Err(ApiError::UnexpectedResponse),
-        }
     }
 }
@@ -0,0 +1,539 @@
+//! # Backup module
+//!
+//! This module exports users to JSON Lines or CSV and imports them back, to back up users or move them
+//! between panels.
+//!
+//! Both formats share the same columns: `username`, `status`, `data_limit`, `used_traffic`, `expire`,
+//! `proxies`, `inbounds`, `note` and `admin`. In CSV, `proxies` and `inbounds` are JSON objects and
+//! empty cells are unset values.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! use marzban_api::{
+//!     backup::{self, ConflictStrategy, ExportFormat, ImportOptions},
+//!     client::MarzbanAPIClient,
+//! };
+//!
+//! # async fn example(source: MarzbanAPIClient, target: MarzbanAPIClient) -> Result<(), Box<dyn std::error::Error>> {
+//! let users = source.export_users().await?;
+//! backup::write_users(File::create("users.csv")?, &users, ExportFormat::Csv)?;
+//!
+//! let users = backup::read_users(File::open("users.csv")?, ExportFormat::Csv)?;
+//! let options = ImportOptions {
+//!     conflict: ConflictStrategy::Rename,
+//!     ..Default::default()
+//! };
+//! let report = target.import_users(users, options).await?;
+//! println!("{} renamed", report.renamed.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, Read, Write},
+};
+
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use validator::ValidationErrors;
+
+use crate::{
+    api::user::GetUsersQueryParams,
+    bulk::{run_bulk, BulkOptions, BulkReport},
+    client::MarzbanAPIClient,
+    error::{ApiError, BackupError},
+    models::{
+        base::parse_nullable,
+        units::{ByteSize, Expiry},
+        user::{
+            Inbounds, Proxies, UserCreate, UserModify, UserResponse, UserStatus, UserStatusModify,
+        },
+    },
+};
+
+/// The columns of both formats, in order.
+const COLUMNS: [&str; 9] = [
+    "username",
+    "status",
+    "data_limit",
+    "used_traffic",
+    "expire",
+    "proxies",
+    "inbounds",
+    "note",
+    "admin",
+];
+
+/// The file format of exported users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    JsonLines,
+    /// A header row followed by one row per user.
+    Csv,
+}
+
+/// A user as exported, see the [module documentation](self) for the columns.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ExportedUser {
+    pub username: String,
+    pub status: UserStatus,
+    /// Max data usage, `0` means unlimited.
+    #[serde(default, deserialize_with = "parse_nullable")]
+    pub data_limit: ByteSize,
+    /// Informational, the usage is not restored on import.
+    #[serde(default, deserialize_with = "parse_nullable")]
+    pub used_traffic: ByteSize,
+    #[serde(default)]
+    pub expire: Expiry,
+    #[serde(default)]
+    pub proxies: Proxies,
+    #[serde(default)]
+    pub inbounds: Inbounds,
+    pub note: Option<String>,
+    /// The username of the owner admin. Informational, imported users are owned by the importing admin.
+    pub admin: Option<String>,
+}
+
+impl From<&UserResponse> for ExportedUser {
+    fn from(user: &UserResponse) -> Self {
+        ExportedUser {
+            username: user.username.clone(),
+            status: user.status,
+            data_limit: user.data_limit,
+            used_traffic: user.used_traffic,
+            expire: user.expire,
+            proxies: user.proxies.clone(),
+            inbounds: user.inbounds.clone(),
+            note: user.note.clone(),
+            admin: Some(user.admin.username.clone()),
+        }
+    }
+}
+
+impl ExportedUser {
+    /// The request creating this user with the same proxy credentials.
+    ///
+    /// Users are created `active`, as Marzban recomputes the `limited`, `expired` and `on_hold` statuses.
+    pub fn to_user_create(&self) -> Result<UserCreate, ValidationErrors> {
+        let mut builder = UserCreate::builder(&self.username)
+            .proxies(self.proxies.clone())
+            .inbounds(self.inbounds.clone())
+            .data_limit(self.data_limit)
+            .expire(self.expire);
+        if let Some(note) = &self.note {
+            builder = builder.note(note);
+        }
+        builder.build()
+    }
+
+    /// The modification overwriting an existing user with this one.
+    pub fn to_user_modify(&self) -> UserModify {
+        UserModify {
+            proxies: Some(self.proxies.clone()),
+            inbounds: Some(self.inbounds.clone()),
+            expire: Some(self.expire),
+            data_limit: Some(self.data_limit),
+            note: self.note.clone(),
+            status: Some(if self.status == UserStatus::Disabled {
+                UserStatusModify::Disabled
+            } else {
+                UserStatusModify::Active
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// A CSV row, as CSV has no nested values.
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    username: String,
+    status: UserStatus,
+    #[serde(deserialize_with = "parse_nullable")]
+    data_limit: ByteSize,
+    #[serde(deserialize_with = "parse_nullable")]
+    used_traffic: ByteSize,
+    expire: Expiry,
+    proxies: String,
+    inbounds: String,
+    note: Option<String>,
+    admin: Option<String>,
+}
+
+/// Write `users` to `writer` in the given format.
+pub fn write_users(
+    writer: impl Write,
+    users: &[ExportedUser],
+    format: ExportFormat,
+) -> Result<(), BackupError> {
+    match format {
+        ExportFormat::JsonLines => {
+            let mut writer = writer;
+            for (line, user) in (1..).zip(users) {
+                serde_json::to_writer(&mut writer, user)
+                    .map_err(|source| BackupError::Json { line, source })?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Csv => {
+            // The header is written by hand, so it is there even without users.
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(writer);
+            writer.write_record(COLUMNS)?;
+            for (line, user) in (2..).zip(users) {
+                writer.serialize(CsvRow {
+                    username: user.username.clone(),
+                    status: user.status,
+                    data_limit: user.data_limit,
+                    used_traffic: user.used_traffic,
+                    expire: user.expire,
+                    proxies: serde_json::to_string(&user.proxies)
+                        .map_err(|source| BackupError::Json { line, source })?,
+                    inbounds: serde_json::to_string(&user.inbounds)
+                        .map_err(|source| BackupError::Json { line, source })?,
+                    note: user.note.clone(),
+                    admin: user.admin.clone(),
+                })?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Read users from `reader` in the given format. Empty JSON lines are skipped.
+pub fn read_users(
+    reader: impl Read,
+    format: ExportFormat,
+) -> Result<Vec<ExportedUser>, BackupError> {
+    let mut users = Vec::new();
+    match format {
+        ExportFormat::JsonLines => {
+            for (line, text) in (1..).zip(BufReader::new(reader).lines()) {
+                let text = text?;
+                if text.trim().is_empty() {
+                    continue;
+                }
+                users.push(
+                    serde_json::from_str(&text)
+                        .map_err(|source| BackupError::Json { line, source })?,
+                );
+            }
+        }
+        ExportFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(reader);
+            let headers = reader.headers()?.clone();
+            for record in reader.records() {
+                let record = record?;
+                let line = record.position().map_or(0, |position| position.line());
+                let row: CsvRow = record.deserialize(Some(&headers))?;
+                let json = |value: &str| match value.trim() {
+                    "" => Ok(serde_json::Value::Null),
+                    value => serde_json::from_str(value)
+                        .map_err(|source| BackupError::Json { line, source }),
+                };
+                let (proxies, inbounds) = (json(&row.proxies)?, json(&row.inbounds)?);
+                users.push(ExportedUser {
+                    username: row.username,
+                    status: row.status,
+                    data_limit: row.data_limit,
+                    used_traffic: row.used_traffic,
+                    expire: row.expire,
+                    proxies: serde_json::from_value(proxies)
+                        .map_err(|source| BackupError::Json { line, source })?,
+                    inbounds: serde_json::from_value(inbounds)
+                        .map_err(|source| BackupError::Json { line, source })?,
+                    note: row.note,
+                    admin: row.admin,
+                });
+            }
+        }
+    }
+    Ok(users)
+}
+
+/// What to do when an imported user already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictStrategy {
+    /// Keep the existing user.
+    #[default]
+    Skip,
+    /// Overwrite the existing user, see [`ExportedUser::to_user_modify()`].
+    Overwrite,
+    /// Import under the first free `{username}_{n}`, with new proxy credentials.
+    Rename,
+}
+
+/// Options for [`MarzbanAPIClient::import_users()`].
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Defaults to [`ConflictStrategy::Skip`].
+    pub conflict: ConflictStrategy,
+    /// The concurrency and stop on errors of the import.
+    pub bulk: BulkOptions,
+}
+
+/// The outcome of [`MarzbanAPIClient::import_users()`].
+#[derive(Debug)]
+pub struct ImportReport {
+    /// The created or overwritten users, by their final username.
+    pub imported: BulkReport<UserResponse>,
+    /// Users which already existed, or were listed more than once.
+    pub skipped: Vec<String>,
+    /// The original and new username of renamed users.
+    pub renamed: Vec<(String, String)>,
+}
+
+enum ImportAction {
+    Create {
+        user: Result<UserCreate, ValidationErrors>,
+        disabled: bool,
+    },
+    Overwrite(UserModify),
+}
+
+/// The requests of an import, and the users skipped or renamed on the way.
+struct ImportPlan {
+    actions: Vec<(String, ImportAction)>,
+    skipped: Vec<String>,
+    renamed: Vec<(String, String)>,
+}
+
+/// Plan the import of `users` into a panel with the `existing` usernames.
+fn plan_import(
+    users: impl IntoIterator<Item = ExportedUser>,
+    existing: &HashSet<String>,
+    conflict: ConflictStrategy,
+) -> ImportPlan {
+    let users: Vec<_> = users.into_iter().collect();
+    // Renamed users must not take the name of a user imported later on either.
+    let mut taken: HashSet<String> = existing
+        .iter()
+        .chain(users.iter().map(|user| &user.username))
+        .cloned()
+        .collect();
+    let mut imported = HashSet::new();
+    let (mut skipped, mut renamed, mut actions) = (Vec::new(), Vec::new(), Vec::new());
+    for mut user in users {
+        let disabled = user.status == UserStatus::Disabled;
+        if !imported.insert(user.username.clone()) {
+            skipped.push(user.username);
+            continue;
+        }
+        if existing.contains(&user.username) {
+            match conflict {
+                ConflictStrategy::Skip => {
+                    skipped.push(user.username);
+                    continue;
+                }
+                ConflictStrategy::Overwrite => {
+                    let modify = user.to_user_modify();
+                    actions.push((user.username, ImportAction::Overwrite(modify)));
+                    continue;
+                }
+                ConflictStrategy::Rename => {
+                    let username = free_username(&user.username, &taken);
+                    taken.insert(username.clone());
+                    renamed.push((user.username, username.clone()));
+                    user.username = username;
+                    user.proxies = without_credentials(user.proxies);
+                }
+            }
+        }
+        let action = ImportAction::Create {
+            user: user.to_user_create(),
+            disabled,
+        };
+        actions.push((user.username, action));
+    }
+    ImportPlan {
+        actions,
+        skipped,
+        renamed,
+    }
+}
+
+/// The first `{username}_{n}` which is not taken, within the username length limit.
+fn free_username(username: &str, taken: &HashSet<String>) -> String {
+    (1..)
+        .map(|n| {
+            let suffix = format!("_{n}");
+            let base: String = username.chars().take(32 - suffix.len()).collect();
+            base + &suffix
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .expect("Infinite candidates")
+}
+
+/// Clear the credentials, so the server generates new ones.
+fn without_credentials(mut proxies: Proxies) -> Proxies {
+    if let Some(vmess) = &mut proxies.vmess {
+        vmess.id = None;
+    }
+    if let Some(vless) = &mut proxies.vless {
+        vless.id = None;
+    }
+    if let Some(trojan) = &mut proxies.trojan {
+        trojan.password = None;
+    }
+    if let Some(shadowsocks) = &mut proxies.shadowsocks {
+        shadowsocks.password = None;
+    }
+    proxies
+}
+
+impl MarzbanAPIClient {
+    /// `GET /api/users` (paginated)
+    ///
+    /// Export the users of the current admin, see [`write_users()`] to write them to a file.
+    pub async fn export_users(&self) -> Result<Vec<ExportedUser>, ApiError> {
+        self.users_stream(GetUsersQueryParams::default())
+            .map_ok(|user| ExportedUser::from(&user))
+            .try_collect()
+            .await
+    }
+
+    /// `POST /api/user` and `PUT /api/user/{username}` (bulk)
+    ///
+    /// Import users, e.g. read with [`read_users()`], handling existing users as configured.
+    /// Disabled users are created and disabled right after.
+    pub async fn import_users(
+        &self,
+        users: impl IntoIterator<Item = ExportedUser>,
+        options: ImportOptions,
+    ) -> Result<ImportReport, ApiError> {
+        let existing: HashSet<String> = self
+            .users_stream(GetUsersQueryParams::default())
+            .map_ok(|user| user.username)
+            .try_collect()
+            .await?;
+        let plan = plan_import(users, &existing, options.conflict);
+
+        let imported = run_bulk(plan.actions, options.bulk, |username, action| async move {
+            match action {
+                ImportAction::Create { user, disabled } => {
+                    let created = self.add_user(user?).await?;
+                    if !disabled {
+                        return Ok(created);
+                    }
+                    let modify = UserModify {
+                        status: Some(UserStatusModify::Disabled),
+                        ..Default::default()
+                    };
+                    self.modify_user(username, modify).await
+                }
+                ImportAction::Overwrite(modify) => self.modify_user(username, modify).await,
+            }
+        })
+        .await;
+        Ok(ImportReport {
+            imported,
+            skipped: plan.skipped,
+            renamed: plan.renamed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::user::Vless;
+
+    fn users() -> Vec<ExportedUser> {
+        vec![
+            ExportedUser {
+                username: "alice".to_string(),
+                status: UserStatus::Disabled,
+                data_limit: ByteSize::gib(1),
+                used_traffic: ByteSize::kib(1),
+                expire: Expiry::from_timestamp(1_900_000_000),
+                proxies: Proxies {
+                    vless: Some(Vless {
+                        id: Some("35e4e39c-7d5c-4f4b-8b71-558e4f37ff53".to_string()),
+                        flow: None,
+                    }),
+                    ..Default::default()
+                },
+                inbounds: Inbounds {
+                    vless: Some(vec!["VLESS TCP REALITY".to_string()]),
+                    ..Default::default()
+                },
+                note: Some("Paid, until \"June\"".to_string()),
+                admin: Some("admin".to_string()),
+            },
+            ExportedUser {
+                username: "bob".to_string(),
+                status: UserStatus::Active,
+                data_limit: ByteSize::ZERO,
+                used_traffic: ByteSize::ZERO,
+                expire: Expiry::Never,
+                proxies: Proxies::default(),
+                inbounds: Inbounds::default(),
+                note: None,
+                admin: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trips_formats() {
+        for format in [ExportFormat::JsonLines, ExportFormat::Csv] {
+            let mut buffer = Vec::new();
+            write_users(&mut buffer, &users(), format).unwrap();
+            assert_eq!(read_users(buffer.as_slice(), format).unwrap(), users());
+        }
+
+        let mut buffer = Vec::new();
+        write_users(&mut buffer, &[], ExportFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "username,status,data_limit,used_traffic,expire,proxies,inbounds,note,admin\n"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_lines() {
+        let jsonl = "{\"username\": \"bob\", \"status\": \"active\"}\n\n{";
+        assert!(matches!(
+            read_users(jsonl.as_bytes(), ExportFormat::JsonLines),
+            Err(BackupError::Json { line: 3, .. })
+        ));
+        let csv = "username,status,data_limit,used_traffic,expire,proxies,inbounds,note,admin\n\
+                   bob,active,,0,,{,,,\n";
+        assert!(matches!(
+            read_users(csv.as_bytes(), ExportFormat::Csv),
+            Err(BackupError::Json { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn renames_within_limit() {
+        let taken = HashSet::from(["alice_1".to_string()]);
+        assert_eq!(free_username("alice", &taken), "alice_2");
+        assert_eq!(free_username(&"a".repeat(32), &taken).len(), 32);
+    }
+
+    #[test]
+    fn renames_around_imported_usernames() {
+        let [alice, _] = users().try_into().unwrap();
+        let alice_1 = ExportedUser {
+            username: "alice_1".to_string(),
+            ..alice.clone()
+        };
+        let existing = HashSet::from(["alice".to_string()]);
+        let plan = plan_import([alice, alice_1], &existing, ConflictStrategy::Rename);
+        assert_eq!(
+            plan.renamed,
+            vec![("alice".to_string(), "alice_2".to_string())]
+        );
+        let created: Vec<_> = plan.actions.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(created, ["alice_2", "alice_1"]);
+    }
+}
@@ -0,0 +1,198 @@
+//! # Bulk module
+//!
+//! This module contains the types for running many user operations at once, see
+//! [`MarzbanAPIClient::bulk_add_users()`](crate::client::MarzbanAPIClient::bulk_add_users),
+//! [`MarzbanAPIClient::bulk_modify_users()`](crate::client::MarzbanAPIClient::bulk_modify_users) and
+//! [`MarzbanAPIClient::bulk_delete_users()`](crate::client::MarzbanAPIClient::bulk_delete_users).
+
+use std::{
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use futures::{stream, StreamExt};
+
+use crate::error::ApiError;
+
+/// Options for the `bulk_*_with_options` methods.
+#[derive(Debug, Clone)]
+pub struct BulkOptions {
+    /// Maximum number of requests in flight at once. Defaults to `8`, `0` is treated as `1`.
+    pub concurrency: usize,
+    /// Stop starting new operations after the first failure, the remaining ones are reported
+    /// as [`BulkOutcome::Skipped`]. Operations already in flight still complete. Defaults to `false`.
+    pub stop_on_error: bool,
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        BulkOptions {
+            concurrency: 8,
+            stop_on_error: false,
+        }
+    }
+}
+
+/// The outcome of a single operation of a bulk operation.
+#[derive(Debug)]
+pub enum BulkOutcome<T> {
+    Succeeded(T),
+    Failed(ApiError),
+    /// Not attempted, as an earlier operation failed with [`BulkOptions::stop_on_error`] set.
+    Skipped,
+}
+
+/// The outcome of every operation of a bulk operation, by username in the input order.
+#[derive(Debug)]
+pub struct BulkReport<T> {
+    pub results: Vec<(String, BulkOutcome<T>)>,
+}
+
+impl<T> BulkReport<T> {
+    /// Whether every operation succeeded.
+    pub fn is_success(&self) -> bool {
+        self.results
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, BulkOutcome::Succeeded(_)))
+    }
+
+    /// The outcome for `username`.
+    pub fn get(&self, username: &str) -> Option<&BulkOutcome<T>> {
+        self.results
+            .iter()
+            .find(|(name, _)| name == username)
+            .map(|(_, outcome)| outcome)
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.results
+            .iter()
+            .filter_map(|(username, outcome)| match outcome {
+                BulkOutcome::Succeeded(value) => Some((username.as_str(), value)),
+                _ => None,
+            })
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &ApiError)> {
+        self.results
+            .iter()
+            .filter_map(|(username, outcome)| match outcome {
+                BulkOutcome::Failed(error) => Some((username.as_str(), error)),
+                _ => None,
+            })
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &str> {
+        self.results
+            .iter()
+            .filter_map(|(username, outcome)| match outcome {
+                BulkOutcome::Skipped => Some(username.as_str()),
+                _ => None,
+            })
+    }
+}
+
+/// Run `operation` for every item, with at most `options.concurrency` in flight at once.
+pub(crate) async fn run_bulk<I, T, F, Fut>(
+    items: Vec<(String, I)>,
+    options: BulkOptions,
+    operation: F,
+) -> BulkReport<T>
+where
+    F: Fn(String, I) -> Fut,
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    let stopped = AtomicBool::new(false);
+    let mut results: Vec<_> = stream::iter(items.into_iter().enumerate())
+        .map(|(index, (username, item))| {
+            let (stopped, operation) = (&stopped, &operation);
+            async move {
+                // Checked when the operation is about to start, not when it was queued.
+                if stopped.load(Ordering::Relaxed) {
+                    return (index, username, BulkOutcome::Skipped);
+                }
+                match operation(username.clone(), item).await {
+                    Ok(value) => (index, username, BulkOutcome::Succeeded(value)),
+                    Err(error) => {
+                        log::debug!("Bulk operation for {username} failed: {error}");
+                        if options.stop_on_error {
+                            stopped.store(true, Ordering::Relaxed);
+                        }
+                        (index, username, BulkOutcome::Failed(error))
+                    }
+                }
+            }
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+    results.sort_by_key(|(index, ..)| *index);
+    BulkReport {
+        results: results
+            .into_iter()
+            .map(|(_, username, outcome)| (username, outcome))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn limits_concurrency_and_keeps_order() {
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+        let items = (0..20).map(|i| (format!("user{i}"), i)).collect();
+        let options = BulkOptions {
+            concurrency: 3,
+            ..Default::default()
+        };
+        let report = run_bulk(items, options, |_, i| {
+            let (in_flight, max_in_flight) = (&in_flight, &max_in_flight);
+            async move {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(20 - i)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if i == 5 {
+                    Err(ApiError::NotFound("User not found".to_string()))
+                } else {
+                    Ok(i)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+        assert_eq!(report.results[19].0, "user19");
+        assert_eq!(report.succeeded().count(), 19);
+        assert!(matches!(report.get("user5"), Some(BulkOutcome::Failed(_))));
+        assert!(!report.is_success());
+    }
+
+    #[tokio::test]
+    async fn stops_on_error() {
+        let items = (0..5).map(|i| (format!("user{i}"), i)).collect();
+        let options = BulkOptions {
+            concurrency: 1,
+            stop_on_error: true,
+        };
+        let report = run_bulk(items, options, |_, i| async move {
+            match i {
+                1 => Err(ApiError::Conflict("User already exists".to_string())),
+                _ => Ok(()),
+            }
+        })
+        .await;
+
+        assert_eq!(report.succeeded().count(), 1);
+        assert_eq!(report.failed().count(), 1);
+        assert_eq!(
+            report.skipped().collect::<Vec<_>>(),
+            ["user2", "user3", "user4"]
+        );
+    }
+}
@@ -2,10 +2,15 @@
 //!
 //! This module contains the API client for the Marzban API.
 
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, future::Future, pin::Pin, sync::Arc, time::Duration};
 
-use reqwest::{Client, IntoUrl};
-use tokio::sync::RwLock;
+use reqwest::{
+    header::{HeaderValue, AUTHORIZATION},
+    Certificate, Client, IntoUrl, Proxy, Request, RequestBuilder, Response, StatusCode,
+};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::{error::ApiError, models::auth::BodyAdminTokenApiAdminTokenPost, retry::RetryPolicy};
 
 /// The Marzban API client.
 ///
@@ -24,6 +29,10 @@ pub(crate) struct MarzbanAPIClientRef {
     pub(crate) base_url: String,
     pub(crate) client: Client,
     pub(crate) token: RwLock<Option<String>>,
+    pub(crate) credentials: RwLock<Option<Credentials>>,
+    /// Held while a new token is being issued, so concurrent requests only re-authenticate once.
+    pub(crate) reauth_lock: Mutex<()>,
+    pub(crate) retry_policy: RetryPolicy,
 }
 
 impl Debug for MarzbanAPIClientRef {
@@ -32,6 +41,8 @@ impl Debug for MarzbanAPIClientRef {
             .field("base_url", &self.base_url)
             .field("client", &self.client)
             .field("token", &"*****")
+            .field("credentials", &"*****")
+            .field("retry_policy", &self.retry_policy)
             .finish()
     }
 }
@@ -44,6 +55,9 @@ impl MarzbanAPIClient {
                 base_url: base_url.to_string(),
                 client: Client::new(),
                 token: RwLock::new(None),
+                credentials: RwLock::new(None),
+                reauth_lock: Mutex::new(()),
+                retry_policy: RetryPolicy::none(),
             }
             .into(),
         }
@@ -56,11 +70,31 @@ impl MarzbanAPIClient {
                 base_url: base_url.to_string(),
                 client: Client::new(),
                 token: RwLock::new(Some(token.to_owned())),
+                credentials: RwLock::new(None),
+                reauth_lock: Mutex::new(()),
+                retry_policy: RetryPolicy::none(),
             }
             .into(),
         }
     }
 
+    /// Create a [`MarzbanAPIClientBuilder`] for the given base URL.
+    ///
+    /// Use this instead of [`MarzbanAPIClient::new()`] when you need to configure timeouts,
+    /// proxies, TLS or supply your own [`reqwest::Client`].
+    pub fn builder(base_url: &str) -> MarzbanAPIClientBuilder {
+        MarzbanAPIClientBuilder::new(base_url)
+    }
+
+    /// Set (or clear) the credentials used to automatically re-authenticate.
+    ///
+    /// When credentials are set and a request is rejected with `401 Unauthorized`, the client
+    /// issues a new token with [`MarzbanAPIClient::admin_token()`] and retries the request once.
+    /// This also covers clients which have not authenticated yet.
+    pub async fn set_credentials(&self, credentials: Option<Credentials>) {
+        *self.inner.credentials.write().await = credentials;
+    }
+
     /// Helper method to create a request with authorization header if token is present
     pub(crate) async fn prepare_authorized_request(
         &self,
@@ -73,4 +107,369 @@ impl MarzbanAPIClient {
         }
         request_builder
     }
+
+    /// Helper method to send a request, retrying it according to the [`RetryPolicy`], and
+    /// re-authenticating and retrying once on `401 Unauthorized` if `reauthenticate` is set
+    /// and credentials are configured.
+    pub(crate) async fn send_request(
+        &self,
+        request: RequestBuilder,
+        reauthenticate: bool,
+    ) -> Result<Response, ApiError> {
+        let request = request.build()?;
+        let retry = request.try_clone();
+        let used_token = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_owned);
+
+        let response = self.execute_with_retries(request).await?;
+        if !reauthenticate || response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let Some(mut retry) = retry else {
+            return Ok(response);
+        };
+        if self.inner.credentials.read().await.is_none() {
+            return Ok(response);
+        }
+
+        log::debug!("Token rejected, re-authenticating");
+        let token = self.reauthenticate(used_token.as_deref()).await?;
+        let header = HeaderValue::from_str(&format!("Bearer {token}")).map_err(|_| {
+            ApiError::Unauthorized("Issued token is not a valid header".to_string())
+        })?;
+        retry.headers_mut().insert(AUTHORIZATION, header);
+        self.execute_with_retries(retry).await
+    }
+
+    /// Execute a request, retrying it according to the [`RetryPolicy`].
+    async fn execute_with_retries(&self, request: Request) -> Result<Response, ApiError> {
+        let policy = &self.inner.retry_policy;
+        if !policy.allows(request.method()) {
+            return Ok(self.inner.client.execute(request).await?);
+        }
+
+        let mut attempt = 1;
+        loop {
+            let Some(current) = request.try_clone() else {
+                return Ok(self.inner.client.execute(request).await?);
+            };
+            let delay = match self.inner.client.execute(current).await {
+                Ok(response) => match policy.response_delay(&response, attempt) {
+                    Some(delay) if attempt < policy.max_attempts => delay,
+                    _ => return Ok(response),
+                },
+                Err(error)
+                    if attempt < policy.max_attempts && policy.is_retryable_error(&error) =>
+                {
+                    policy.backoff(attempt)
+                }
+                Err(error) => return Err(error.into()),
+            };
+            log::debug!("Attempt {attempt} failed, retrying in {delay:?}");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Issue and store a new token, unless another request already replaced the `stale` one.
+    pub(crate) async fn reauthenticate(&self, stale: Option<&str>) -> Result<String, ApiError> {
+        let _guard = self.inner.reauth_lock.lock().await;
+        if let Some(current) = self.inner.token.read().await.as_deref() {
+            if Some(current) != stale {
+                return Ok(current.to_owned());
+            }
+        }
+
+        let credentials = self.inner.credentials.read().await.clone();
+        let Some(credentials) = credentials else {
+            return Err(ApiError::Unauthorized(
+                "No credentials to re-authenticate with".to_string(),
+            ));
+        };
+        // Boxed, as issuing the token goes through the same request path.
+        let token = Box::pin(self.admin_token(credentials.resolve().await?)).await?;
+        *self.inner.token.write().await = Some(token.access_token.clone());
+        Ok(token.access_token)
+    }
+}
+
+type CredentialsFuture =
+    Pin<Box<dyn Future<Output = Result<BodyAdminTokenApiAdminTokenPost, ApiError>> + Send>>;
+
+/// Admin credentials remembered by the client to re-issue a token once it has expired.
+#[derive(Clone)]
+pub enum Credentials {
+    /// Fixed credentials.
+    Static(BodyAdminTokenApiAdminTokenPost),
+    /// A callback which is invoked every time a new token has to be issued,
+    /// e.g. to fetch the password from a secret store.
+    Provider(Arc<dyn Fn() -> CredentialsFuture + Send + Sync>),
+}
+
+impl Credentials {
+    /// Create [`Credentials::Provider`] from an async callback.
+    pub fn provider<F, Fut>(provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<BodyAdminTokenApiAdminTokenPost, ApiError>> + Send + 'static,
+    {
+        Credentials::Provider(Arc::new(move || Box::pin(provider())))
+    }
+
+    async fn resolve(&self) -> Result<BodyAdminTokenApiAdminTokenPost, ApiError> {
+        match self {
+            Credentials::Static(auth) => Ok(auth.clone()),
+            Credentials::Provider(provider) => provider().await,
+        }
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::Static(auth) => f
+                .debug_struct("Static")
+                .field("username", &auth.username)
+                .field("password", &"*****")
+                .finish(),
+            Credentials::Provider(_) => f.write_str("Provider"),
+        }
+    }
+}
+
+/// A builder for [`MarzbanAPIClient`].
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use marzban_api::client::MarzbanAPIClient;
+///
+/// let client = MarzbanAPIClient::builder("https://panel.example.com")
+///     .timeout(Duration::from_secs(10))
+///     .connect_timeout(Duration::from_secs(3))
+///     .proxy(reqwest::Proxy::all("socks5h://127.0.0.1:1080").unwrap())
+///     .user_agent("my-bot/1.0")
+///     .build()
+///     .expect("Failed to build client");
+/// ```
+#[derive(Debug)]
+pub struct MarzbanAPIClientBuilder {
+    base_url: String,
+    token: Option<String>,
+    credentials: Option<Credentials>,
+    retry_policy: RetryPolicy,
+    client: Option<Client>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxies: Vec<Proxy>,
+    root_certificates: Vec<Certificate>,
+    tls_built_in_root_certs: bool,
+    danger_accept_invalid_certs: bool,
+    user_agent: Option<String>,
+}
+
+impl MarzbanAPIClientBuilder {
+    /// Create a new builder for the given base URL.
+    pub fn new(base_url: &str) -> Self {
+        MarzbanAPIClientBuilder {
+            base_url: base_url.to_string(),
+            token: None,
+            credentials: None,
+            retry_policy: RetryPolicy::none(),
+            client: None,
+            timeout: None,
+            connect_timeout: None,
+            proxies: Vec::new(),
+            root_certificates: Vec::new(),
+            tls_built_in_root_certs: true,
+            danger_accept_invalid_certs: false,
+            user_agent: None,
+        }
+    }
+
+    /// Use an existing bearer token for requests.
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_owned());
+        self
+    }
+
+    /// Remember admin credentials to automatically re-authenticate when the token expires.
+    ///
+    /// See [`MarzbanAPIClient::set_credentials()`].
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Retry failed requests according to the given [`RetryPolicy`].
+    ///
+    /// Defaults to [`RetryPolicy::none()`].
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Use a pre-built [`reqwest::Client`].
+    ///
+    /// ## Note
+    ///
+    /// When a client is supplied, the HTTP options of this builder (timeouts, proxies, TLS and
+    /// user agent) are ignored, as they have to be configured on the supplied client instead.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Set a timeout for each request, from connecting until the response body has finished.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set a timeout for only the connect phase of each request.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Route requests through an outbound HTTP(S) or SOCKS5 proxy.
+    ///
+    /// Can be called multiple times to add several proxies.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Trust an additional root certificate, e.g. for panels using a self-signed certificate.
+    ///
+    /// Can be called multiple times to add several certificates.
+    pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Controls the use of the built-in/system root certificates. Defaults to `true`.
+    ///
+    /// Disable this together with [`MarzbanAPIClientBuilder::add_root_certificate()`] to only
+    /// trust your own certificate authority.
+    pub fn tls_built_in_root_certs(mut self, enabled: bool) -> Self {
+        self.tls_built_in_root_certs = enabled;
+        self
+    }
+
+    /// Disable TLS certificate validation entirely. Defaults to `false`.
+    ///
+    /// ## Warning
+    ///
+    /// Any certificate, including expired or forged ones, will be trusted. Prefer
+    /// [`MarzbanAPIClientBuilder::add_root_certificate()`] whenever possible.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.danger_accept_invalid_certs = accept;
+        self
+    }
+
+    /// Set the `User-Agent` header sent with each request.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_owned());
+        self
+    }
+
+    /// Build the [`MarzbanAPIClient`].
+    ///
+    /// Fails with [`ApiError::NetworkError`] if the underlying [`reqwest::Client`] could not be
+    /// built, e.g. because the TLS backend could not be initialized.
+    pub fn build(self) -> Result<MarzbanAPIClient, ApiError> {
+        let client = match self.client {
+            Some(client) => client,
+            None => {
+                let mut builder = Client::builder()
+                    .tls_built_in_root_certs(self.tls_built_in_root_certs)
+                    .danger_accept_invalid_certs(self.danger_accept_invalid_certs);
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                for proxy in self.proxies {
+                    builder = builder.proxy(proxy);
+                }
+                for certificate in self.root_certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+                if let Some(user_agent) = self.user_agent {
+                    builder = builder.user_agent(user_agent);
+                }
+                builder.build()?
+            }
+        };
+
+        Ok(MarzbanAPIClient {
+            inner: MarzbanAPIClientRef {
+                base_url: self.base_url,
+                client,
+                token: RwLock::new(self.token),
+                credentials: RwLock::new(self.credentials),
+                reauth_lock: Mutex::new(()),
+                retry_policy: self.retry_policy,
+            }
+            .into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serve `responses` in order, one per connection, and count the requests received.
+    async fn serve(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0; 4096];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn builder_applies_retry_policy() {
+        let unavailable =
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let ok = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]";
+        let (url, requests) = serve(vec![unavailable, unavailable, ok]).await;
+
+        let client = MarzbanAPIClient::builder(&url)
+            .token("token")
+            .retry_policy(RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let admins = client.get_admins(None, None, None::<String>).await.unwrap();
+        assert!(admins.is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
 }
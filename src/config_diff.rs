@@ -0,0 +1,401 @@
+//! # Config diff module
+//!
+//! This module compares two Xray core configurations, to review what a
+//! [`MarzbanAPIClient::modify_xray_config()`](crate::client::MarzbanAPIClient::modify_xray_config)
+//! would change before the core and all nodes are restarted.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use marzban_api::client::MarzbanAPIClient;
+//!
+//! # async fn example(client: MarzbanAPIClient) -> Result<(), marzban_api::error::ApiError> {
+//! let mut config = client.get_xray_config().await?;
+//! config.outbounds.retain(|outbound| outbound.tag.as_deref() != Some("BLOCK"));
+//!
+//! // Validate and diff against the current config, without applying it.
+//! let diff = client.apply_xray_config(&config, true).await?;
+//! println!("{diff}");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::xray::{Inbound, Outbound, RoutingRule, XrayConfig};
+
+/// A change to an inbound or outbound, identified by its tag.
+///
+/// Repeated tags are numbered, e.g. `DIRECT (2)`, and untagged items are identified by their
+/// position, e.g. `#2`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T> {
+    Added {
+        tag: String,
+        after: T,
+    },
+    Removed {
+        tag: String,
+        before: T,
+    },
+    Modified {
+        tag: String,
+        before: T,
+        after: T,
+        /// The changed fields, as dotted JSON paths, e.g. `streamSettings.security`.
+        fields: Vec<String>,
+    },
+}
+
+impl<T> Change<T> {
+    /// The tag of the changed inbound or outbound.
+    pub fn tag(&self) -> &str {
+        match self {
+            Change::Added { tag, .. }
+            | Change::Removed { tag, .. }
+            | Change::Modified { tag, .. } => tag,
+        }
+    }
+}
+
+/// A routing rule which was added or removed, with its position in the respective config.
+///
+/// Rules have no identity, so a modified rule shows up as removed and added.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleChange {
+    Added { index: usize, rule: RoutingRule },
+    Removed { index: usize, rule: RoutingRule },
+}
+
+/// The differences between two Xray core configurations.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub inbounds: Vec<Change<Inbound>>,
+    pub outbounds: Vec<Change<Outbound>>,
+    pub rules: Vec<RuleChange>,
+    /// The same rules are present, but in a different order. Rules are matched in order.
+    pub rules_reordered: bool,
+    /// Other changed fields, as dotted JSON paths, e.g. `log.loglevel` or `routing.domainStrategy`.
+    pub other: Vec<String>,
+}
+
+impl ConfigDiff {
+    /// Whether both configurations are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.inbounds.is_empty()
+            && self.outbounds.is_empty()
+            && self.rules.is_empty()
+            && !self.rules_reordered
+            && self.other.is_empty()
+    }
+}
+
+impl std::fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write_changes<T>(
+            f: &mut std::fmt::Formatter<'_>,
+            kind: &str,
+            changes: &[Change<T>],
+        ) -> std::fmt::Result {
+            for change in changes {
+                match change {
+                    Change::Added { tag, .. } => writeln!(f, "+ {kind} {tag}")?,
+                    Change::Removed { tag, .. } => writeln!(f, "- {kind} {tag}")?,
+                    Change::Modified { tag, fields, .. } => {
+                        writeln!(f, "~ {kind} {tag}: {}", fields.join(", "))?
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        write_changes(f, "inbound", &self.inbounds)?;
+        write_changes(f, "outbound", &self.outbounds)?;
+        for change in &self.rules {
+            match change {
+                RuleChange::Added { index, rule } => {
+                    writeln!(f, "+ rule #{index} {}", describe(rule))?
+                }
+                RuleChange::Removed { index, rule } => {
+                    writeln!(f, "- rule #{index} {}", describe(rule))?
+                }
+            }
+        }
+        if self.rules_reordered {
+            writeln!(f, "~ rules reordered")?;
+        }
+        for path in &self.other {
+            writeln!(f, "~ {path}")?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(rule: &RoutingRule) -> String {
+    match (&rule.outbound_tag, &rule.balancer_tag) {
+        (Some(outbound), _) => format!("-> {outbound}"),
+        (None, Some(balancer)) => format!("-> balancer {balancer}"),
+        (None, None) => "-> ?".to_string(),
+    }
+}
+
+fn to_value(value: &impl Serialize) -> Value {
+    // The config models only have string keys, so they always serialize into a `Value`.
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// Collect the dotted paths of the fields which differ between `before` and `after`.
+///
+/// A missing field differs from an explicit `null`, as both are kept by the config models.
+fn changed_paths(before: &Value, after: &Value, prefix: &str, paths: &mut Vec<String>) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            let keys: std::collections::BTreeSet<_> = before.keys().chain(after.keys()).collect();
+            for key in keys {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                match (before.get(key), after.get(key)) {
+                    (Some(before), Some(after)) => changed_paths(before, after, &path, paths),
+                    _ => paths.push(path),
+                }
+            }
+        }
+        (before, after) if before != after => paths.push(prefix.to_string()),
+        _ => {}
+    }
+}
+
+/// Key items by their tag. Repeated tags are numbered, e.g. `DIRECT (2)`, so duplicates are
+/// diffed rather than collapsed. Untagged items are identified by their position, e.g. `#2`.
+fn keyed<'a, T>(items: &'a [T], tag: &impl Fn(&T) -> Option<&String>) -> Vec<(String, &'a T)> {
+    let mut seen = BTreeMap::new();
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let key = match tag(item) {
+                Some(tag) => {
+                    let count = seen.entry(tag).or_insert(0);
+                    *count += 1;
+                    if *count == 1 {
+                        tag.clone()
+                    } else {
+                        format!("{tag} ({count})")
+                    }
+                }
+                None => format!("#{index}"),
+            };
+            (key, item)
+        })
+        .collect()
+}
+
+/// Diff tagged items, see [`keyed()`] for how they are identified.
+fn diff_tagged<T: Clone + PartialEq + Serialize>(
+    before: &[T],
+    after: &[T],
+    tag: impl Fn(&T) -> Option<&String>,
+) -> Vec<Change<T>> {
+    let before = keyed(before, &tag);
+    let after = keyed(after, &tag);
+    let before_by_tag: BTreeMap<_, _> = before.iter().cloned().collect();
+    let after_by_tag: BTreeMap<_, _> = after.iter().cloned().collect();
+
+    let mut changes = Vec::new();
+    for (tag, item) in before {
+        match after_by_tag.get(&tag) {
+            None => changes.push(Change::Removed {
+                tag,
+                before: item.clone(),
+            }),
+            Some(after) if *after != item => {
+                let mut fields = Vec::new();
+                changed_paths(&to_value(item), &to_value(*after), "", &mut fields);
+                changes.push(Change::Modified {
+                    tag,
+                    before: item.clone(),
+                    after: (*after).clone(),
+                    fields,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    for (tag, item) in after {
+        if !before_by_tag.contains_key(&tag) {
+            changes.push(Change::Added {
+                tag,
+                after: item.clone(),
+            });
+        }
+    }
+    changes
+}
+
+/// Diff rules as multisets, reporting every unmatched rule.
+fn diff_rules(before: &[RoutingRule], after: &[RoutingRule]) -> (Vec<RuleChange>, bool) {
+    let mut unmatched: Vec<Option<&RoutingRule>> = after.iter().map(Some).collect();
+    let mut changes = Vec::new();
+    for (index, rule) in before.iter().enumerate() {
+        match unmatched.iter().position(|other| *other == Some(rule)) {
+            Some(position) => unmatched[position] = None,
+            None => changes.push(RuleChange::Removed {
+                index,
+                rule: rule.clone(),
+            }),
+        }
+    }
+    changes.extend(
+        unmatched
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, rule)| {
+                rule.map(|rule| RuleChange::Added {
+                    index,
+                    rule: rule.clone(),
+                })
+            }),
+    );
+    let reordered = changes.is_empty() && before != after;
+    (changes, reordered)
+}
+
+impl XrayConfig {
+    /// Compare this configuration with a `proposed` one.
+    pub fn diff(&self, proposed: &XrayConfig) -> ConfigDiff {
+        let (rules, rules_reordered) = diff_rules(self.rules(), proposed.rules());
+
+        // Everything but the inbounds, outbounds and rules, which are diffed above.
+        let rest = |config: &XrayConfig| {
+            let mut value = to_value(config);
+            if let Value::Object(map) = &mut value {
+                map.remove("inbounds");
+                map.remove("outbounds");
+                if let Some(Value::Object(routing)) = map.get_mut("routing") {
+                    routing.remove("rules");
+                }
+            }
+            value
+        };
+        let mut other = Vec::new();
+        changed_paths(&rest(self), &rest(proposed), "", &mut other);
+
+        ConfigDiff {
+            inbounds: diff_tagged(&self.inbounds, &proposed.inbounds, |i| i.tag.as_ref()),
+            outbounds: diff_tagged(&self.outbounds, &proposed.outbounds, |o| o.tag.as_ref()),
+            rules,
+            rules_reordered,
+            other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn config() -> XrayConfig {
+        serde_json::from_value(json!({
+            "log": { "loglevel": "warning" },
+            "inbounds": [
+                { "tag": "VMess TCP", "port": 8080, "protocol": "vmess", "settings": { "clients": [] } },
+                { "tag": "Trojan", "port": 2083, "protocol": "trojan", "settings": { "clients": [] } }
+            ],
+            "outbounds": [
+                { "protocol": "freedom", "tag": "DIRECT" },
+                { "protocol": "blackhole", "tag": "BLOCK" }
+            ],
+            "routing": {
+                "rules": [
+                    { "type": "field", "ip": ["geoip:private"], "outboundTag": "BLOCK" },
+                    { "type": "field", "protocol": ["bittorrent"], "outboundTag": "BLOCK" }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn identical_configs_have_no_changes() {
+        let diff = config().diff(&config());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "No changes\n");
+    }
+
+    #[test]
+    fn reports_changes() {
+        let mut proposed = config();
+        proposed.log.as_mut().unwrap().loglevel = Some("error".to_string());
+        proposed.inbounds[0].port = Some(crate::models::proxy::ProxyInboundPort::Integer(8081));
+        proposed.inbounds.remove(1);
+        proposed
+            .outbounds
+            .push(serde_json::from_value(json!({ "protocol": "freedom", "tag": "IPv4" })).unwrap());
+        let rules = proposed.routing.as_mut().unwrap().rules.as_mut().unwrap();
+        rules.remove(1);
+
+        let diff = config().diff(&proposed);
+        assert_eq!(diff.inbounds.len(), 2);
+        assert!(
+            matches!(&diff.inbounds[0], Change::Modified { fields, .. } if fields == &["port"])
+        );
+        assert!(matches!(&diff.inbounds[1], Change::Removed { tag, .. } if tag == "Trojan"));
+        assert!(matches!(&diff.outbounds[..], [Change::Added { tag, .. }] if tag == "IPv4"));
+        assert!(matches!(
+            diff.rules[..],
+            [RuleChange::Removed { index: 1, .. }]
+        ));
+        assert_eq!(diff.other, ["log.loglevel"]);
+        assert_eq!(
+            diff.to_string(),
+            "~ inbound VMess TCP: port\n- inbound Trojan\n+ outbound IPv4\n- rule #1 -> BLOCK\n~ log.loglevel\n"
+        );
+    }
+
+    #[test]
+    fn reports_explicit_nulls() {
+        let mut proposed = config();
+        proposed.extra.insert("stats".to_string(), Value::Null);
+        let diff = config().diff(&proposed);
+        assert_eq!(diff.other, ["stats"]);
+        assert!(config().diff(&config()).is_empty());
+    }
+
+    #[test]
+    fn reports_duplicate_tags() {
+        let mut current = config();
+        current.outbounds.push(current.outbounds[0].clone());
+        let diff = current.diff(&config());
+        assert!(
+            matches!(&diff.outbounds[..], [Change::Removed { tag, .. }] if tag == "DIRECT (2)")
+        );
+        assert_eq!(config().diff(&current).outbounds[0].tag(), "DIRECT (2)");
+    }
+
+    #[test]
+    fn detects_reordered_rules() {
+        let mut proposed = config();
+        proposed
+            .routing
+            .as_mut()
+            .unwrap()
+            .rules
+            .as_mut()
+            .unwrap()
+            .reverse();
+        let diff = config().diff(&proposed);
+        assert!(diff.rules.is_empty());
+        assert!(diff.rules_reordered);
+    }
+}
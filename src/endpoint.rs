@@ -0,0 +1,199 @@
+//! # Endpoint module
+//!
+//! This module contains the internal endpoint abstraction every API method goes through,
+//! so authorization, retries, logging and error mapping behave the same for all of them.
+
+use std::marker::PhantomData;
+
+use reqwest::{
+    header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    Method, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{client::MarzbanAPIClient, error::ApiError};
+
+/// How the body of a successful response is decoded.
+pub(crate) trait Decode {
+    type Output;
+
+    fn decode(
+        response: Response,
+    ) -> impl std::future::Future<Output = Result<Self::Output, ApiError>> + Send;
+}
+
+/// Decode the response body as JSON into `T`.
+pub(crate) struct Json<T>(PhantomData<fn() -> T>);
+
+impl<T: DeserializeOwned> Decode for Json<T> {
+    type Output = T;
+
+    async fn decode(response: Response) -> Result<T, ApiError> {
+        Ok(response.json::<T>().await?)
+    }
+}
+
+/// Return the response body as text.
+pub(crate) struct Text;
+
+impl Decode for Text {
+    type Output = String;
+
+    async fn decode(response: Response) -> Result<String, ApiError> {
+        Ok(response.text().await?)
+    }
+}
+
+/// Return the response as is, for methods which need its headers.
+pub(crate) struct Raw;
+
+impl Decode for Raw {
+    type Output = Response;
+
+    async fn decode(response: Response) -> Result<Response, ApiError> {
+        Ok(response)
+    }
+}
+
+enum Body {
+    Json(Vec<u8>),
+    Form(String),
+}
+
+/// A request to a single API endpoint, decoding a successful response with `R`.
+pub(crate) struct Endpoint<R> {
+    method: Method,
+    path: String,
+    query: Vec<(&'static str, String)>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Option<Body>,
+    reauthenticate: bool,
+    response: PhantomData<fn() -> R>,
+}
+
+impl<R: Decode> Endpoint<R> {
+    /// An endpoint with the given method and path, relative to the base URL.
+    pub(crate) fn new(method: Method, path: impl Into<String>) -> Self {
+        Endpoint {
+            method,
+            path: path.into(),
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: None,
+            reauthenticate: true,
+            response: PhantomData,
+        }
+    }
+
+    pub(crate) fn get(path: impl Into<String>) -> Self {
+        Self::new(Method::GET, path)
+    }
+
+    pub(crate) fn post(path: impl Into<String>) -> Self {
+        Self::new(Method::POST, path)
+    }
+
+    pub(crate) fn put(path: impl Into<String>) -> Self {
+        Self::new(Method::PUT, path)
+    }
+
+    pub(crate) fn delete(path: impl Into<String>) -> Self {
+        Self::new(Method::DELETE, path)
+    }
+
+    /// Append a query parameter.
+    pub(crate) fn query(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.query.push((key, value.to_string()));
+        self
+    }
+
+    /// Append a query parameter if the value is present.
+    pub(crate) fn query_opt(self, key: &'static str, value: Option<impl ToString>) -> Self {
+        match value {
+            Some(value) => self.query(key, value),
+            None => self,
+        }
+    }
+
+    /// Append several query parameters, keys may repeat.
+    pub(crate) fn query_pairs(mut self, pairs: Vec<(&'static str, String)>) -> Self {
+        self.query.extend(pairs);
+        self
+    }
+
+    /// Set a request header, overriding the client's default.
+    pub(crate) fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Send the given value as a JSON body.
+    pub(crate) fn json(mut self, body: &(impl Serialize + ?Sized)) -> Result<Self, ApiError> {
+        self.body = Some(Body::Json(serde_json::to_vec(body)?));
+        Ok(self)
+    }
+
+    /// Send the given value as a `application/x-www-form-urlencoded` body.
+    pub(crate) fn form(mut self, body: &impl Serialize) -> Result<Self, ApiError> {
+        let body = serde_urlencoded::to_string(body)
+            .map_err(|error| ApiError::Serialization(serde::ser::Error::custom(error)))?;
+        self.body = Some(Body::Form(body));
+        Ok(self)
+    }
+
+    /// Do not re-authenticate on `401 Unauthorized`, used by the token endpoint itself.
+    pub(crate) fn without_reauthentication(mut self) -> Self {
+        self.reauthenticate = false;
+        self
+    }
+}
+
+impl MarzbanAPIClient {
+    /// Send the request described by the endpoint, and decode the response with `R`
+    /// on `200 OK`, or map it to the matching [`ApiError`] otherwise.
+    pub(crate) async fn execute<R: Decode>(
+        &self,
+        endpoint: Endpoint<R>,
+    ) -> Result<R::Output, ApiError> {
+        let url = format!("{}{}", self.inner.base_url, endpoint.path);
+        let mut request = self
+            .prepare_authorized_request(endpoint.method.clone(), url)
+            .await;
+        if !endpoint.query.is_empty() {
+            request = request.query(&endpoint.query);
+        }
+        for (name, value) in endpoint.headers {
+            request = request.header(name, value);
+        }
+        request = match endpoint.body {
+            Some(Body::Json(body)) => request
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(body),
+            Some(Body::Form(body)) => request
+                .header(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("application/x-www-form-urlencoded"),
+                )
+                .body(body),
+            None => request,
+        };
+
+        let response = self
+            .send_request(request, endpoint.reauthenticate)
+            .await
+            .inspect_err(|error| {
+                log::debug!("{} {} failed: {error}", endpoint.method, endpoint.path)
+            })?;
+        log::debug!(
+            "{} {} returned {}",
+            endpoint.method,
+            endpoint.path,
+            response.status()
+        );
+
+        match response.status() {
+            StatusCode::OK => R::decode(response).await,
+            _ => Err(ApiError::from_response(endpoint.method, response).await),
+        }
+    }
+}
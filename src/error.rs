@@ -2,16 +2,233 @@
 //!
 //! This module contains the error types for the Marzban API client.
 
+use reqwest::{Method, Response, StatusCode};
+use serde::Deserialize;
 use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+use validator::ValidationErrors;
+
+use crate::models::errors::HTTPValidationError;
 
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("Network error: {0}")]
     NetworkError(#[from] reqwest::Error),
 
-    #[error("API error: {0}")]
-    ApiResponseError(String),
+    /// `401 Unauthorized`, the token is missing, invalid or expired.
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// `403 Forbidden`, the current admin is not allowed to perform this action.
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    /// `404 Not Found`, e.g. the user, admin, node or template does not exist.
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    /// `409 Conflict`, e.g. the user, admin or template already exists.
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// `422 Unprocessable Entity`, the request was rejected by the server side validation.
+    #[error("Validation error: {0:?}")]
+    Validation(HTTPValidationError),
+
+    /// A body could not be serialized or deserialized.
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    /// The request was not sent, as it failed the client side validation.
+    #[error("Client validation error: {0}")]
+    ClientValidation(#[from] ValidationErrors),
+
+    /// A subscription body could not be decoded for its client type.
+    #[error("Subscription decode error: {0}")]
+    SubscriptionDecode(#[from] SubscriptionDecodeError),
+
+    /// The websocket connection failed, e.g. while streaming logs.
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+
+    /// The server closed the websocket with an error code, e.g. `4400` for an invalid interval.
+    #[error("WebSocket closed with code {code}: {reason}")]
+    WebSocketClosed { code: u16, reason: String },
+
+    /// Any other response which the API method did not expect.
+    #[error("Unexpected API response: {method} {url} returned {status}: {body}")]
+    Unexpected {
+        status: StatusCode,
+        method: Method,
+        url: String,
+        body: String,
+    },
+}
+
+/// A share link could not be parsed, see [`crate::links`].
+#[derive(Debug, Error)]
+pub enum LinkParseError {
+    #[error("Unsupported share link scheme: {0}")]
+    UnsupportedScheme(String),
+
+    #[error("Share link is missing the {0}")]
+    Missing(&'static str),
+
+    #[error("Invalid port: {0}")]
+    InvalidPort(String),
+
+    #[error("Invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("Invalid UTF-8: {0}")]
+    Utf8(#[from] std::str::Utf8Error),
+
+    /// The base64 encoded JSON of a `vmess://` link is invalid.
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A byte size could not be parsed, see [`crate::models::units::ByteSize`].
+#[derive(Debug, Error)]
+pub enum ByteSizeParseError {
+    #[error("Invalid byte size: {0}")]
+    Invalid(String),
+
+    #[error("Unknown byte size unit: {0}")]
+    UnknownUnit(String),
+}
+
+/// A subscription body could not be decoded, see [`crate::models::subscription`].
+#[derive(Debug, Error)]
+pub enum SubscriptionDecodeError {
+    #[error("Invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("Invalid UTF-8: {0}")]
+    Utf8(#[from] std::str::Utf8Error),
+
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid YAML: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+/// Users could not be written or read, see [`crate::backup`].
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid JSON on line {line}: {source}")]
+    Json {
+        line: u64,
+        source: serde_json::Error,
+    },
+
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+}
+
+/// A QR code could not be rendered, see [`crate::qr`].
+#[cfg(feature = "qr")]
+#[derive(Debug, Error)]
+pub enum QrError {
+    /// The data does not fit in a QR code.
+    #[error("QR encode error: {0}")]
+    Encode(#[from] qrcode::types::QrError),
+
+    #[error("Image error: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+/// The `{"detail": "..."}` body Marzban returns alongside most error responses.
+#[derive(Deserialize)]
+struct ErrorDetail {
+    detail: String,
+}
+
+impl ApiError {
+    /// Map an unsuccessful response into the matching [`ApiError`] variant.
+    ///
+    /// The response body is consumed, and the `detail` message Marzban returns is used when present.
+    pub(crate) async fn from_response(method: Method, response: Response) -> ApiError {
+        let status = response.status();
+        let url = response.url().to_string();
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(error) => return ApiError::NetworkError(error),
+        };
+
+        if status == StatusCode::UNPROCESSABLE_ENTITY {
+            if let Ok(error) = serde_json::from_str::<HTTPValidationError>(&body) {
+                return ApiError::Validation(error);
+            }
+        }
+
+        let detail = serde_json::from_str::<ErrorDetail>(&body)
+            .map(|error| error.detail)
+            .unwrap_or_else(|_| status.canonical_reason().unwrap_or_default().to_string());
+
+        match status {
+            StatusCode::UNAUTHORIZED => ApiError::Unauthorized(detail),
+            StatusCode::FORBIDDEN => ApiError::Forbidden(detail),
+            StatusCode::NOT_FOUND => ApiError::NotFound(detail),
+            StatusCode::CONFLICT => ApiError::Conflict(detail),
+            _ => ApiError::Unexpected {
+                status,
+                method,
+                url,
+                body,
+            },
+        }
+    }
+
+    /// The HTTP status code returned by the server, if the error originates from a response.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ApiError::NetworkError(error) => error.status(),
+            ApiError::Serialization(_)
+            | ApiError::ClientValidation(_)
+            | ApiError::SubscriptionDecode(_)
+            | ApiError::WebSocket(_)
+            | ApiError::WebSocketClosed { .. } => None,
+            ApiError::Unauthorized(_) => Some(StatusCode::UNAUTHORIZED),
+            ApiError::Forbidden(_) => Some(StatusCode::FORBIDDEN),
+            ApiError::NotFound(_) => Some(StatusCode::NOT_FOUND),
+            ApiError::Conflict(_) => Some(StatusCode::CONFLICT),
+            ApiError::Validation(_) => Some(StatusCode::UNPROCESSABLE_ENTITY),
+            ApiError::Unexpected { status, .. } => Some(*status),
+        }
+    }
+}
 
-    #[error("Unexpected API response")]
-    UnexpectedResponse,
+/// Handshake responses are mapped like regular responses, other failures become [`ApiError::WebSocket`].
+impl From<tungstenite::Error> for ApiError {
+    fn from(error: tungstenite::Error) -> Self {
+        match &error {
+            tungstenite::Error::Http(response) => {
+                let detail = response
+                    .body()
+                    .as_deref()
+                    .map(String::from_utf8_lossy)
+                    .filter(|body| !body.is_empty())
+                    .map(|body| body.into_owned())
+                    .unwrap_or_else(|| {
+                        response
+                            .status()
+                            .canonical_reason()
+                            .unwrap_or_default()
+                            .to_string()
+                    });
+                match response.status() {
+                    StatusCode::UNAUTHORIZED => ApiError::Unauthorized(detail),
+                    StatusCode::FORBIDDEN => ApiError::Forbidden(detail),
+                    StatusCode::NOT_FOUND => ApiError::NotFound(detail),
+                    _ => ApiError::WebSocket(Box::new(error)),
+                }
+            }
+            _ => ApiError::WebSocket(Box::new(error)),
+        }
+    }
 }
@@ -45,7 +45,7 @@
 //! #[tokio::main]
 //! async fn main() {
 //!     let client = MarzbanAPIClient::new("http://localhost:8000");
-//!     client.authenticate(&BodyAdminTokenApiAdminTokenPost {
+//!     client.authenticate(BodyAdminTokenApiAdminTokenPost {
 //!         grant_type: Some("password".to_string()),
 //!         username: "admin".to_string(),
 //!         password: "admin".to_string(),
@@ -56,11 +56,63 @@
 //!     // Client is now authenticated and token will be used in future requests
 //! }
 //! ```
+//!
+//! Remember the credentials to transparently re-authenticate once the token expires:
+//!
+//! ```no_run
+//! use marzban_api::client::{Credentials, MarzbanAPIClient};
+//! use marzban_api::models::auth::BodyAdminTokenApiAdminTokenPost;
+//!
+//! let client = MarzbanAPIClient::builder("http://localhost:8000")
+//!     .credentials(Credentials::Static(BodyAdminTokenApiAdminTokenPost {
+//!         grant_type: Some("password".to_string()),
+//!         username: "admin".to_string(),
+//!         password: "admin".to_string(),
+//!         scope: "".to_string(),
+//!         client_id: None,
+//!         client_secret: None,
+//!     }))
+//!     .build()
+//!     .expect("Failed to build client");
+//! // The first request is rejected with 401, after which a token is issued and the request retried
+//! ```
+//!
+//! Configure timeouts, proxies or TLS with the builder:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use marzban_api::client::MarzbanAPIClient;
+//!
+//! let client = MarzbanAPIClient::builder("https://panel.example.com")
+//!     .timeout(Duration::from_secs(10))
+//!     .danger_accept_invalid_certs(true)
+//!     .build()
+//!     .expect("Failed to build client");
+//! ```
+//!
+//! ## Features
+//!
+//! - `mock` - An in-process fake Marzban server (`marzban_api::mock`) to test against without a real panel.
+//! - `qr` - Render share links and subscription URLs to QR codes as PNG, SVG or Unicode (`marzban_api::qr`).
 
 #![forbid(unsafe_code)]
 #![deny(unreachable_pub)]
 
 pub mod api;
+pub mod backup;
+pub mod bulk;
 pub mod client;
+pub mod config_diff;
+mod endpoint;
 pub mod error;
+pub mod links;
+pub mod logs;
+pub mod migrate;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod models;
+#[cfg(feature = "qr")]
+pub mod qr;
+pub mod reconcile;
+pub mod retry;
@@ -0,0 +1,782 @@
+//! # Links module
+//!
+//! This module parses and serializes the share links of
+//! [`UserResponse::links`](crate::models::user::UserResponse::links): `vmess://` (base64 encoded JSON),
+//! `vless://`, `trojan://` and `ss://` (SIP002) URIs.
+//!
+//! ## Example
+//!
+//! ```
+//! use marzban_api::links::ShareLink;
+//!
+//! let link: ShareLink = "trojan://secret@example.com:443?security=tls&type=ws&path=%2Fws&sni=example.com#Trojan"
+//!     .parse()
+//!     .unwrap();
+//! assert_eq!(link.address(), "example.com");
+//! assert_eq!(link.port(), 443);
+//! assert_eq!(link.remark(), "Trojan");
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::{json, Map, Value};
+
+use crate::{error::LinkParseError, models::proxy::ProxyTypes};
+
+/// Characters left unescaped, matching Python's `urllib.parse.quote` which Marzban uses.
+const QUOTE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// The transport of a link, e.g. `tcp`, `ws` or `grpc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transport {
+    /// `tcp`, `ws`, `grpc`, `httpupgrade`, `splithttp`, `quic`, ... Defaults to `tcp`.
+    pub network: String,
+    /// The header type, e.g. `none` or `http`.
+    pub header_type: Option<String>,
+    /// The host header, the gRPC authority or the QUIC security.
+    pub host: Option<String>,
+    /// The path, the gRPC service name or the QUIC key.
+    pub path: Option<String>,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport {
+            network: "tcp".to_string(),
+            header_type: None,
+            host: None,
+            path: None,
+        }
+    }
+}
+
+/// The security of a link, e.g. `none`, `tls` or `reality`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Security {
+    /// `none`, `tls` or `reality`. Defaults to `none`.
+    pub kind: String,
+    pub sni: Option<String>,
+    pub alpn: Vec<String>,
+    /// The uTLS fingerprint, e.g. `chrome`.
+    pub fingerprint: Option<String>,
+    pub allow_insecure: bool,
+    /// The REALITY public key.
+    pub public_key: Option<String>,
+    /// The REALITY short id.
+    pub short_id: Option<String>,
+    /// The REALITY spider X.
+    pub spider_x: Option<String>,
+}
+
+impl Default for Security {
+    fn default() -> Self {
+        Security {
+            kind: "none".to_string(),
+            sni: None,
+            alpn: Vec::new(),
+            fingerprint: None,
+            allow_insecure: false,
+            public_key: None,
+            short_id: None,
+            spider_x: None,
+        }
+    }
+}
+
+/// A `vmess://` link, the base64 encoded JSON used by v2rayN.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmessLink {
+    pub id: String,
+    pub address: String,
+    pub port: u16,
+    pub alter_id: u32,
+    /// The `scy` cipher, e.g. `auto`.
+    pub cipher: Option<String>,
+    pub transport: Transport,
+    pub security: Security,
+    pub remark: String,
+    /// The JSON fields which are not modelled, e.g. the `v` version.
+    pub extra: Map<String, Value>,
+}
+
+/// A `vless://` link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VlessLink {
+    pub id: String,
+    pub address: String,
+    pub port: u16,
+    /// The XTLS flow, e.g. `xtls-rprx-vision`.
+    pub flow: Option<String>,
+    pub transport: Transport,
+    pub security: Security,
+    pub remark: String,
+    /// The query parameters which are not modelled, in order.
+    pub extra: Vec<(String, String)>,
+}
+
+/// A `trojan://` link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrojanLink {
+    pub password: String,
+    pub address: String,
+    pub port: u16,
+    pub transport: Transport,
+    pub security: Security,
+    pub remark: String,
+    /// The query parameters which are not modelled, in order.
+    pub extra: Vec<(String, String)>,
+}
+
+/// A `ss://` link, in the SIP002 format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowsocksLink {
+    pub method: String,
+    pub password: String,
+    pub address: String,
+    pub port: u16,
+    /// The SIP003 plugin and its options, e.g. `obfs-local;obfs=http`.
+    pub plugin: Option<String>,
+    pub remark: String,
+}
+
+/// Any share link Marzban generates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShareLink {
+    Vmess(VmessLink),
+    Vless(VlessLink),
+    Trojan(TrojanLink),
+    Shadowsocks(ShadowsocksLink),
+}
+
+impl ShareLink {
+    pub fn protocol(&self) -> ProxyTypes {
+        match self {
+            ShareLink::Vmess(_) => ProxyTypes::Vmess,
+            ShareLink::Vless(_) => ProxyTypes::Vless,
+            ShareLink::Trojan(_) => ProxyTypes::Trojan,
+            ShareLink::Shadowsocks(_) => ProxyTypes::ShadowSocks,
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        match self {
+            ShareLink::Vmess(link) => &link.address,
+            ShareLink::Vless(link) => &link.address,
+            ShareLink::Trojan(link) => &link.address,
+            ShareLink::Shadowsocks(link) => &link.address,
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        match self {
+            ShareLink::Vmess(link) => link.port,
+            ShareLink::Vless(link) => link.port,
+            ShareLink::Trojan(link) => link.port,
+            ShareLink::Shadowsocks(link) => link.port,
+        }
+    }
+
+    /// The name of the link, shown by clients.
+    pub fn remark(&self) -> &str {
+        match self {
+            ShareLink::Vmess(link) => &link.remark,
+            ShareLink::Vless(link) => &link.remark,
+            ShareLink::Trojan(link) => &link.remark,
+            ShareLink::Shadowsocks(link) => &link.remark,
+        }
+    }
+}
+
+fn decode(value: &str) -> Result<String, LinkParseError> {
+    Ok(percent_decode_str(value).decode_utf8()?.into_owned())
+}
+
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, QUOTE).to_string()
+}
+
+/// Decode standard or URL safe base64, with or without padding.
+pub(crate) fn decode_base64_lenient(value: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    let value: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .collect();
+    STANDARD_NO_PAD
+        .decode(&value)
+        .or_else(|_| URL_SAFE_NO_PAD.decode(&value))
+}
+
+fn decode_base64(value: &str) -> Result<String, LinkParseError> {
+    let bytes = decode_base64_lenient(value)?;
+    Ok(String::from_utf8(bytes).map_err(|error| error.utf8_error())?)
+}
+
+fn parse_port(port: &str) -> Result<u16, LinkParseError> {
+    port.parse()
+        .map_err(|_| LinkParseError::InvalidPort(port.to_string()))
+}
+
+/// Split `host:port`, where IPv6 hosts are enclosed in brackets.
+fn split_host_port(value: &str) -> Result<(String, u16), LinkParseError> {
+    let (host, port) = value
+        .rsplit_once(':')
+        .ok_or(LinkParseError::Missing("port"))?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return Err(LinkParseError::Missing("address"));
+    }
+    Ok((decode(host)?, parse_port(port)?))
+}
+
+fn format_host(address: &str) -> String {
+    if address.contains(':') {
+        format!("[{address}]")
+    } else {
+        address.to_string()
+    }
+}
+
+/// The parts of a `scheme://userinfo@host:port?query#remark` URI.
+struct UriParts {
+    userinfo: Option<String>,
+    authority: String,
+    query: Vec<(String, String)>,
+    remark: String,
+}
+
+impl UriParts {
+    /// Split the URI, with its scheme already stripped. The userinfo is left encoded.
+    fn parse(rest: &str) -> Result<UriParts, LinkParseError> {
+        let (rest, remark) = rest.split_once('#').unwrap_or((rest, ""));
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let rest = rest.trim_end_matches('/');
+        let (userinfo, authority) = match rest.rsplit_once('@') {
+            Some((userinfo, authority)) => (Some(userinfo.to_string()), authority),
+            None => (None, rest),
+        };
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                // `urlencode` encodes spaces as `+`.
+                Ok((
+                    decode(&key.replace('+', " "))?,
+                    decode(&value.replace('+', " "))?,
+                ))
+            })
+            .collect::<Result<_, LinkParseError>>()?;
+        Ok(UriParts {
+            userinfo,
+            authority: authority.to_string(),
+            query,
+            remark: decode(remark)?,
+        })
+    }
+
+    /// Remove and return the query parameter `key`.
+    fn take(&mut self, key: &str) -> Option<String> {
+        let index = self.query.iter().position(|(k, _)| k == key)?;
+        Some(self.query.remove(index).1)
+    }
+
+    fn take_transport(&mut self) -> Transport {
+        let network = self.take("type").unwrap_or_else(|| "tcp".to_string());
+        let (path_key, host_key) = path_and_host_keys(&network);
+        Transport {
+            header_type: self.take("headerType"),
+            path: self.take(path_key),
+            host: self.take(host_key),
+            network,
+        }
+    }
+
+    fn take_security(&mut self) -> Security {
+        Security {
+            kind: self.take("security").unwrap_or_else(|| "none".to_string()),
+            sni: self.take("sni"),
+            alpn: split_alpn(self.take("alpn").as_deref()),
+            fingerprint: self.take("fp"),
+            allow_insecure: self
+                .take("allowInsecure")
+                .is_some_and(|value| value == "1" || value == "true"),
+            public_key: self.take("pbk"),
+            short_id: self.take("sid"),
+            spider_x: self.take("spx"),
+        }
+    }
+}
+
+/// The query parameters holding the path and host, which depend on the network.
+fn path_and_host_keys(network: &str) -> (&'static str, &'static str) {
+    match network {
+        "grpc" => ("serviceName", "authority"),
+        "quic" => ("key", "quicSecurity"),
+        _ => ("path", "host"),
+    }
+}
+
+fn split_alpn(alpn: Option<&str>) -> Vec<String> {
+    alpn.into_iter()
+        .flat_map(|alpn| alpn.split(','))
+        .filter(|alpn| !alpn.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Write the URI in the parameter order Marzban uses.
+fn write_uri(
+    f: &mut fmt::Formatter<'_>,
+    scheme: &str,
+    userinfo: &str,
+    address: &str,
+    port: u16,
+    params: Vec<(&str, &str)>,
+    remark: &str,
+) -> fmt::Result {
+    write!(
+        f,
+        "{scheme}://{}@{}:{port}",
+        encode(userinfo),
+        format_host(address)
+    )?;
+    for (index, (key, value)) in params.iter().enumerate() {
+        let separator = if index == 0 { '?' } else { '&' };
+        write!(f, "{separator}{}={}", encode(key), encode(value))?;
+    }
+    write!(f, "#{}", encode(remark))
+}
+
+fn push_transport<'a>(params: &mut Vec<(&'a str, &'a str)>, transport: &'a Transport) {
+    params.push(("type", &transport.network));
+    if let Some(header_type) = &transport.header_type {
+        params.push(("headerType", header_type));
+    }
+}
+
+fn push_path_and_host<'a>(params: &mut Vec<(&'a str, &'a str)>, transport: &'a Transport) {
+    let (path_key, host_key) = path_and_host_keys(&transport.network);
+    if let Some(path) = &transport.path {
+        params.push((path_key, path));
+    }
+    if let Some(host) = &transport.host {
+        params.push((host_key, host));
+    }
+}
+
+fn push_security<'a>(params: &mut Vec<(&'a str, &'a str)>, security: &'a Security, alpn: &'a str) {
+    let optional = [("sni", &security.sni), ("fp", &security.fingerprint)];
+    params.extend(
+        optional
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_deref()?))),
+    );
+    if !alpn.is_empty() {
+        params.push(("alpn", alpn));
+    }
+    if security.allow_insecure {
+        params.push(("allowInsecure", "1"));
+    }
+    let reality = [
+        ("pbk", &security.public_key),
+        ("sid", &security.short_id),
+        ("spx", &security.spider_x),
+    ];
+    params.extend(
+        reality
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_deref()?))),
+    );
+}
+
+fn strip_scheme<'a>(link: &'a str, scheme: &str) -> Result<&'a str, LinkParseError> {
+    link.trim()
+        .strip_prefix(scheme)
+        .and_then(|rest| rest.strip_prefix("://"))
+        .ok_or_else(|| {
+            LinkParseError::UnsupportedScheme(
+                link.split("://").next().unwrap_or_default().to_string(),
+            )
+        })
+}
+
+impl FromStr for VmessLink {
+    type Err = LinkParseError;
+
+    fn from_str(link: &str) -> Result<Self, Self::Err> {
+        let mut config: Map<String, Value> =
+            serde_json::from_str(&decode_base64(strip_scheme(link, "vmess")?)?)?;
+        // Values are strings or numbers, depending on the generator.
+        let mut take = |key: &str| match config.remove(key) {
+            Some(Value::String(value)) if !value.is_empty() => Some(value),
+            Some(Value::Number(value)) => Some(value.to_string()),
+            Some(Value::Bool(value)) => Some(value.to_string()),
+            _ => None,
+        };
+
+        let port = take("port").ok_or(LinkParseError::Missing("port"))?;
+        let alter_id = take("aid").map(|aid| aid.parse().unwrap_or_default());
+        let transport = Transport {
+            network: take("net").unwrap_or_else(|| "tcp".to_string()),
+            header_type: take("type"),
+            host: take("host"),
+            path: take("path"),
+        };
+        let security = Security {
+            kind: take("tls").unwrap_or_else(|| "none".to_string()),
+            sni: take("sni"),
+            alpn: split_alpn(take("alpn").as_deref()),
+            fingerprint: take("fp"),
+            allow_insecure: take("allowInsecure").is_some_and(|v| v == "1" || v == "true"),
+            public_key: take("pbk"),
+            short_id: take("sid"),
+            spider_x: take("spx"),
+        };
+        Ok(VmessLink {
+            id: take("id").ok_or(LinkParseError::Missing("id"))?,
+            address: take("add").ok_or(LinkParseError::Missing("address"))?,
+            port: parse_port(&port)?,
+            alter_id: alter_id.unwrap_or_default(),
+            cipher: take("scy"),
+            transport,
+            security,
+            remark: take("ps").unwrap_or_default(),
+            extra: config,
+        })
+    }
+}
+
+impl fmt::Display for VmessLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut config = self.extra.clone();
+        let mut insert = |key: &str, value: Value| {
+            config.insert(key.to_string(), value);
+        };
+        insert("add", json!(self.address));
+        insert("aid", json!(self.alter_id.to_string()));
+        insert("id", json!(self.id));
+        insert("net", json!(self.transport.network));
+        insert("port", json!(self.port));
+        insert("ps", json!(self.remark));
+        insert("tls", json!(self.security.kind));
+        insert(
+            "host",
+            json!(self.transport.host.as_deref().unwrap_or_default()),
+        );
+        insert(
+            "path",
+            json!(self.transport.path.as_deref().unwrap_or_default()),
+        );
+        let optional = [
+            ("scy", &self.cipher),
+            ("type", &self.transport.header_type),
+            ("sni", &self.security.sni),
+            ("fp", &self.security.fingerprint),
+            ("pbk", &self.security.public_key),
+            ("sid", &self.security.short_id),
+            ("spx", &self.security.spider_x),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                insert(key, json!(value));
+            }
+        }
+        if !self.security.alpn.is_empty() {
+            insert("alpn", json!(self.security.alpn.join(",")));
+        }
+        if self.security.allow_insecure {
+            insert("allowInsecure", json!(1));
+        }
+        write!(
+            f,
+            "vmess://{}",
+            STANDARD.encode(Value::Object(config).to_string())
+        )
+    }
+}
+
+impl FromStr for VlessLink {
+    type Err = LinkParseError;
+
+    fn from_str(link: &str) -> Result<Self, Self::Err> {
+        let mut parts = UriParts::parse(strip_scheme(link, "vless")?)?;
+        let id = parts
+            .userinfo
+            .as_deref()
+            .ok_or(LinkParseError::Missing("id"))?;
+        let (address, port) = split_host_port(&parts.authority)?;
+        Ok(VlessLink {
+            id: decode(id)?,
+            address,
+            port,
+            flow: parts.take("flow"),
+            transport: parts.take_transport(),
+            security: parts.take_security(),
+            remark: std::mem::take(&mut parts.remark),
+            extra: parts.query,
+        })
+    }
+}
+
+impl fmt::Display for VlessLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alpn = self.security.alpn.join(",");
+        let mut params = vec![("security", self.security.kind.as_str())];
+        push_transport(&mut params, &self.transport);
+        if let Some(flow) = &self.flow {
+            params.push(("flow", flow));
+        }
+        push_path_and_host(&mut params, &self.transport);
+        push_security(&mut params, &self.security, &alpn);
+        params.extend(self.extra.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        write_uri(
+            f,
+            "vless",
+            &self.id,
+            &self.address,
+            self.port,
+            params,
+            &self.remark,
+        )
+    }
+}
+
+impl FromStr for TrojanLink {
+    type Err = LinkParseError;
+
+    fn from_str(link: &str) -> Result<Self, Self::Err> {
+        let mut parts = UriParts::parse(strip_scheme(link, "trojan")?)?;
+        let password = parts
+            .userinfo
+            .as_deref()
+            .ok_or(LinkParseError::Missing("password"))?;
+        let (address, port) = split_host_port(&parts.authority)?;
+        Ok(TrojanLink {
+            password: decode(password)?,
+            address,
+            port,
+            transport: parts.take_transport(),
+            security: parts.take_security(),
+            remark: std::mem::take(&mut parts.remark),
+            extra: parts.query,
+        })
+    }
+}
+
+impl fmt::Display for TrojanLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alpn = self.security.alpn.join(",");
+        let mut params = vec![("security", self.security.kind.as_str())];
+        push_transport(&mut params, &self.transport);
+        push_path_and_host(&mut params, &self.transport);
+        push_security(&mut params, &self.security, &alpn);
+        params.extend(self.extra.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        write_uri(
+            f,
+            "trojan",
+            &self.password,
+            &self.address,
+            self.port,
+            params,
+            &self.remark,
+        )
+    }
+}
+
+impl FromStr for ShadowsocksLink {
+    type Err = LinkParseError;
+
+    fn from_str(link: &str) -> Result<Self, Self::Err> {
+        let mut parts = UriParts::parse(strip_scheme(link, "ss")?)?;
+        let (userinfo, authority) = match parts.userinfo.take() {
+            Some(userinfo) => {
+                let userinfo = decode(&userinfo)?;
+                // SIP002 allows plain `method:password` for AEAD-2022 ciphers.
+                let userinfo = if userinfo.contains(':') {
+                    userinfo
+                } else {
+                    decode_base64(&userinfo)?
+                };
+                (userinfo, parts.authority.clone())
+            }
+            // The legacy format encodes `method:password@host:port` as a whole.
+            None => {
+                let decoded = decode_base64(&parts.authority)?;
+                let (userinfo, authority) = decoded
+                    .rsplit_once('@')
+                    .ok_or(LinkParseError::Missing("password"))?;
+                (userinfo.to_string(), authority.to_string())
+            }
+        };
+        let (method, password) = userinfo
+            .split_once(':')
+            .ok_or(LinkParseError::Missing("password"))?;
+        let (address, port) = split_host_port(&authority)?;
+        Ok(ShadowsocksLink {
+            method: method.to_string(),
+            password: password.to_string(),
+            address,
+            port,
+            plugin: parts.take("plugin"),
+            remark: parts.remark,
+        })
+    }
+}
+
+impl fmt::Display for ShadowsocksLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let userinfo = STANDARD.encode(format!("{}:{}", self.method, self.password));
+        write!(
+            f,
+            "ss://{userinfo}@{}:{}",
+            format_host(&self.address),
+            self.port
+        )?;
+        if let Some(plugin) = &self.plugin {
+            write!(f, "?plugin={}", encode(plugin))?;
+        }
+        write!(f, "#{}", encode(&self.remark))
+    }
+}
+
+impl FromStr for ShareLink {
+    type Err = LinkParseError;
+
+    fn from_str(link: &str) -> Result<Self, Self::Err> {
+        let scheme = link.trim().split("://").next().unwrap_or_default();
+        match scheme {
+            "vmess" => link.parse().map(ShareLink::Vmess),
+            "vless" => link.parse().map(ShareLink::Vless),
+            "trojan" => link.parse().map(ShareLink::Trojan),
+            "ss" => link.parse().map(ShareLink::Shadowsocks),
+            scheme => Err(LinkParseError::UnsupportedScheme(scheme.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ShareLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareLink::Vmess(link) => link.fmt(f),
+            ShareLink::Vless(link) => link.fmt(f),
+            ShareLink::Trojan(link) => link.fmt(f),
+            ShareLink::Shadowsocks(link) => link.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(link: &str) -> ShareLink {
+        let parsed: ShareLink = link.parse().unwrap();
+        assert_eq!(parsed.to_string().parse::<ShareLink>().unwrap(), parsed);
+        parsed
+    }
+
+    #[test]
+    fn parses_vmess() {
+        let json = r#"{"add": "example.com", "aid": "0", "host": "", "id": "a6f7bb0c-2b4d-4b9a-9c2f-34a3f5f0d1b7", "net": "ws", "path": "/vmess", "port": 8080, "ps": "VMess WS (alice)", "scy": "auto", "tls": "tls", "type": "none", "v": "2", "sni": "example.com", "fp": "chrome", "alpn": "h2,http/1.1"}"#;
+        let link = round_trip(&format!("vmess://{}", STANDARD.encode(json)));
+        let ShareLink::Vmess(vmess) = link else {
+            panic!("Expected a vmess link, got {link:?}");
+        };
+        assert_eq!(vmess.id, "a6f7bb0c-2b4d-4b9a-9c2f-34a3f5f0d1b7");
+        assert_eq!(vmess.port, 8080);
+        assert_eq!(vmess.transport.network, "ws");
+        assert_eq!(vmess.transport.path.as_deref(), Some("/vmess"));
+        assert_eq!(vmess.transport.host, None);
+        assert_eq!(vmess.security.kind, "tls");
+        assert_eq!(vmess.security.alpn, ["h2", "http/1.1"]);
+        assert_eq!(vmess.remark, "VMess WS (alice)");
+        assert_eq!(vmess.extra["v"], "2");
+    }
+
+    #[test]
+    fn parses_vless_reality() {
+        let link = "vless://a6f7bb0c-2b4d-4b9a-9c2f-34a3f5f0d1b7@1.2.3.4:8443?security=reality&type=tcp&headerType=none&flow=xtls-rprx-vision&sni=www.example.com&fp=chrome&pbk=publickey&sid=6ba85179e30d4fc2#VLESS%20TCP%20REALITY%20%28alice%29";
+        assert_eq!(round_trip(link).to_string(), link);
+        let ShareLink::Vless(vless) = link.parse().unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(vless.address, "1.2.3.4");
+        assert_eq!(vless.flow.as_deref(), Some("xtls-rprx-vision"));
+        assert_eq!(vless.security.kind, "reality");
+        assert_eq!(vless.security.public_key.as_deref(), Some("publickey"));
+        assert_eq!(vless.remark, "VLESS TCP REALITY (alice)");
+        assert!(vless.extra.is_empty());
+    }
+
+    #[test]
+    fn parses_trojan_grpc() {
+        let link = "trojan://p%40ss@[2001:db8::1]:443?security=tls&type=grpc&serviceName=trojan&mode=gun&sni=example.com&allowInsecure=1#Trojan";
+        let ShareLink::Trojan(trojan) = round_trip(link) else {
+            unreachable!()
+        };
+        assert_eq!(trojan.password, "p@ss");
+        assert_eq!(trojan.address, "2001:db8::1");
+        assert_eq!(trojan.transport.path.as_deref(), Some("trojan"));
+        assert!(trojan.security.allow_insecure);
+        assert_eq!(trojan.extra, [("mode".to_string(), "gun".to_string())]);
+        assert!(trojan
+            .to_string()
+            .starts_with("trojan://p%40ss@[2001:db8::1]:443?"));
+    }
+
+    #[test]
+    fn parses_shadowsocks() {
+        let userinfo = STANDARD.encode("chacha20-ietf-poly1305:secret");
+        let link = format!("ss://{userinfo}@example.com:1080#Shadowsocks%20TCP");
+        assert_eq!(round_trip(&link).to_string(), link);
+
+        let plain = "ss://2022-blake3-aes-128-gcm:c2VjcmV0@example.com:1080/?plugin=obfs-local%3Bobfs%3Dhttp#SS";
+        let ShareLink::Shadowsocks(ss) = round_trip(plain) else {
+            unreachable!()
+        };
+        assert_eq!(ss.method, "2022-blake3-aes-128-gcm");
+        assert_eq!(ss.password, "c2VjcmV0");
+        assert_eq!(ss.plugin.as_deref(), Some("obfs-local;obfs=http"));
+
+        let legacy = format!(
+            "ss://{}#Legacy",
+            URL_SAFE_NO_PAD.encode("aes-256-gcm:secret@1.2.3.4:8388")
+        );
+        let ShareLink::Shadowsocks(ss) = round_trip(&legacy) else {
+            unreachable!()
+        };
+        assert_eq!((ss.address.as_str(), ss.port), ("1.2.3.4", 8388));
+    }
+
+    #[test]
+    fn rejects_invalid_links() {
+        assert!(matches!(
+            "http://example.com".parse::<ShareLink>(),
+            Err(LinkParseError::UnsupportedScheme(scheme)) if scheme == "http"
+        ));
+        assert!(matches!(
+            "vless://id@example.com?type=tcp".parse::<ShareLink>(),
+            Err(LinkParseError::Missing("port"))
+        ));
+        assert!(matches!(
+            "trojan://pass@example.com:99999".parse::<ShareLink>(),
+            Err(LinkParseError::InvalidPort(_))
+        ));
+        assert!(matches!(
+            "vmess://not base64!".parse::<ShareLink>(),
+            Err(LinkParseError::Base64(_))
+        ));
+    }
+}
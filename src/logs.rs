@@ -0,0 +1,330 @@
+//! # Logs module
+//!
+//! This module contains the types for streaming core and node logs over Marzban's websockets.
+//!
+//! See [`MarzbanAPIClient::core_logs_stream()`] and [`MarzbanAPIClient::node_logs_stream()`].
+
+use std::{collections::VecDeque, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream, StreamExt};
+use reqwest::Url;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{protocol::CloseFrame, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+use validator::{ValidationError, ValidationErrors};
+
+use crate::{client::MarzbanAPIClient, error::ApiError};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A single line of a core or node log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    /// The log line, without the trailing newline.
+    pub message: String,
+    /// When the line was received by the client.
+    pub received_at: DateTime<Utc>,
+}
+
+impl std::fmt::Display for LogLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Options for [`MarzbanAPIClient::core_logs_stream_with_options()`] and
+/// [`MarzbanAPIClient::node_logs_stream_with_options()`].
+#[derive(Debug, Clone)]
+pub struct LogsStreamOptions {
+    /// Let the server batch the lines and send them every `interval`, between 0 and 10 seconds.
+    /// Defaults to [`None`], sending every line as soon as it is logged.
+    pub interval: Option<Duration>,
+    /// Reconnect when an established connection drops. Defaults to `true`.
+    pub reconnect: bool,
+    /// Delay before each reconnection attempt. Defaults to 1 second.
+    pub reconnect_delay: Duration,
+    /// Number of consecutive failed reconnection attempts before the stream gives up. Defaults to `5`.
+    pub max_reconnect_attempts: u32,
+}
+
+impl Default for LogsStreamOptions {
+    fn default() -> Self {
+        LogsStreamOptions {
+            interval: None,
+            reconnect: true,
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_attempts: 5,
+        }
+    }
+}
+
+impl LogsStreamOptions {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self.interval {
+            Some(interval) if interval.is_zero() || interval > Duration::from_secs(10) => {
+                let mut errors = ValidationErrors::new();
+                errors.add(
+                    "interval",
+                    ValidationError::new("range")
+                        .with_message("Interval must be more than 0 and at most 10 seconds".into()),
+                );
+                Err(errors)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Map a close frame sent by Marzban into an error, or [`None`] for a normal closure.
+fn close_error(frame: Option<CloseFrame>) -> Option<ApiError> {
+    let frame = frame?;
+    let reason = frame.reason.to_string();
+    match u16::from(frame.code) {
+        1000 | 1001 => None,
+        4401 => Some(ApiError::Unauthorized(reason)),
+        4403 => Some(ApiError::Forbidden(reason)),
+        4404 => Some(ApiError::NotFound(reason)),
+        code => Some(ApiError::WebSocketClosed { code, reason }),
+    }
+}
+
+/// Split a websocket message into log lines. Batched messages contain one line per row.
+fn split_lines(text: &str) -> impl Iterator<Item = LogLine> + '_ {
+    let received_at = Utc::now();
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(move |line| LogLine {
+            message: line.to_string(),
+            received_at,
+        })
+}
+
+struct LogsStreamState {
+    client: MarzbanAPIClient,
+    path: String,
+    options: LogsStreamOptions,
+    socket: Option<Socket>,
+    pending: VecDeque<LogLine>,
+    connected_before: bool,
+    finished: bool,
+}
+
+impl LogsStreamState {
+    /// The websocket URL of the endpoint, authenticated with the given token.
+    fn url(&self, token: Option<&str>) -> Result<Url, ApiError> {
+        let mut url = self
+            .client
+            .inner
+            .client
+            .get(format!("{}{}", self.client.inner.base_url, self.path))
+            .build()?
+            .url()
+            .clone();
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        // Only fails for special to non-special scheme changes, which http(s) to ws(s) is not.
+        let _ = url.set_scheme(scheme);
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(token) = token {
+                query.append_pair("token", token);
+            }
+            if let Some(interval) = self.options.interval {
+                query.append_pair("interval", &interval.as_secs_f64().to_string());
+            }
+        }
+        Ok(url)
+    }
+
+    async fn try_connect(&self, token: Option<&str>) -> Result<Socket, ApiError> {
+        let url = self.url(token)?;
+        log::debug!("Connecting to {}", self.path);
+        let (socket, _) = connect_async(url.as_str()).await?;
+        Ok(socket)
+    }
+
+    /// Connect with the stored token, re-authenticating once if it is rejected and credentials are configured.
+    async fn connect(&self) -> Result<Socket, ApiError> {
+        let token = self.client.inner.token.read().await.clone();
+        match self.try_connect(token.as_deref()).await {
+            // Marzban rejects the handshake with `403 Forbidden` for invalid tokens, not `401 Unauthorized`.
+            Err(ApiError::Unauthorized(_) | ApiError::Forbidden(_))
+                if self.client.inner.credentials.read().await.is_some() =>
+            {
+                log::debug!("Token rejected, re-authenticating");
+                let token = self.client.reauthenticate(token.as_deref()).await?;
+                self.try_connect(Some(&token)).await
+            }
+            result => result,
+        }
+    }
+
+    /// Reconnect after the connection dropped, giving up after the configured number of attempts.
+    async fn reconnect(&self) -> Result<Socket, ApiError> {
+        let mut attempt = 1;
+        loop {
+            tokio::time::sleep(self.options.reconnect_delay).await;
+            match self.connect().await {
+                Ok(socket) => return Ok(socket),
+                Err(error @ ApiError::WebSocket(_))
+                    if attempt < self.options.max_reconnect_attempts =>
+                {
+                    log::debug!("Reconnection attempt {attempt} failed: {error}");
+                    attempt += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// The connection dropped. Returns whether the stream should reconnect.
+    fn dropped(&mut self) -> bool {
+        self.socket = None;
+        self.finished = !self.options.reconnect;
+        !self.finished
+    }
+
+    async fn next_line(&mut self) -> Option<Result<LogLine, ApiError>> {
+        loop {
+            if let Some(line) = self.pending.pop_front() {
+                return Some(Ok(line));
+            }
+            if self.finished {
+                return None;
+            }
+
+            let socket = match &mut self.socket {
+                Some(socket) => socket,
+                None => {
+                    let connected = if self.connected_before {
+                        self.reconnect().await
+                    } else {
+                        match self.options.validate() {
+                            Ok(()) => self.connect().await,
+                            Err(errors) => Err(errors.into()),
+                        }
+                    };
+                    match connected {
+                        Ok(socket) => {
+                            self.connected_before = true;
+                            self.socket.insert(socket)
+                        }
+                        Err(error) => {
+                            self.finished = true;
+                            return Some(Err(error));
+                        }
+                    }
+                }
+            };
+
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => self.pending.extend(split_lines(&text)),
+                Some(Ok(Message::Binary(data))) => {
+                    self.pending
+                        .extend(split_lines(&String::from_utf8_lossy(&data)));
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    if let Some(error) = close_error(frame) {
+                        self.socket = None;
+                        self.finished = true;
+                        return Some(Err(error));
+                    }
+                    log::debug!("Logs websocket {} closed", self.path);
+                    if !self.dropped() {
+                        return None;
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(error)) => {
+                    log::debug!("Logs websocket {} dropped: {error}", self.path);
+                    if !self.dropped() {
+                        return Some(Err(error.into()));
+                    }
+                }
+                None => {
+                    if !self.dropped() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for LogsStreamState {
+    fn drop(&mut self) {
+        if let Some(mut socket) = self.socket.take() {
+            // Close the connection politely, when dropped inside a runtime.
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn(async move {
+                    let _ = socket.close(None).await;
+                });
+            }
+        }
+    }
+}
+
+impl MarzbanAPIClient {
+    /// Stream the log lines of the websocket at `path`, authenticated with the stored token.
+    pub(crate) fn logs_stream(
+        &self,
+        path: String,
+        options: LogsStreamOptions,
+    ) -> impl Stream<Item = Result<LogLine, ApiError>> {
+        let state = LogsStreamState {
+            client: self.clone(),
+            path,
+            options,
+            socket: None,
+            pending: VecDeque::new(),
+            connected_before: false,
+            finished: false,
+        };
+        stream::unfold(state, |mut state| async move {
+            let line = state.next_line().await?;
+            Some((line, state))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+    use super::*;
+
+    #[test]
+    fn splits_batched_messages() {
+        let lines: Vec<_> = split_lines("first\nsecond\n\n")
+            .map(|line| line.message)
+            .collect();
+        assert_eq!(lines, ["first", "second"]);
+    }
+
+    #[test]
+    fn maps_close_codes() {
+        let frame = |code: u16| {
+            Some(CloseFrame {
+                code: CloseCode::from(code),
+                reason: "reason".into(),
+            })
+        };
+        assert!(close_error(None).is_none());
+        assert!(close_error(frame(1000)).is_none());
+        assert!(matches!(
+            close_error(frame(4401)),
+            Some(ApiError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            close_error(frame(4404)),
+            Some(ApiError::NotFound(_))
+        ));
+        assert!(matches!(
+            close_error(frame(4400)),
+            Some(ApiError::WebSocketClosed { code: 4400, .. })
+        ));
+    }
+}
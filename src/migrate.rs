@@ -0,0 +1,417 @@
+//! # Migrate module
+//!
+//! This module copies users from one panel to another, keeping their proxy credentials so existing
+//! links and subscriptions keep working, see [`migrate_users()`].
+//!
+//! Copied are the proxies, inbounds, expiry, data limit and its reset strategy, note, `on_hold` settings,
+//! whether the user is disabled and the owner admin. As the used traffic can not be set, the data limit on
+//! the target panel is the remaining traffic, unless the data limit is periodically reset, in which case the
+//! full data limit is kept. Whatever could not be copied is reported as a [`MigrationIssue`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use marzban_api::{
+//!     client::MarzbanAPIClient,
+//!     migrate::{migrate_users, MigrationOptions},
+//! };
+//!
+//! # async fn example(source: MarzbanAPIClient, target: MarzbanAPIClient) -> Result<(), marzban_api::error::ApiError> {
+//! let mut options = MigrationOptions::default();
+//! options
+//!     .inbound_map
+//!     .insert("VLESS TCP REALITY".to_string(), "VLESS Reality".to_string());
+//! let report = migrate_users(&source, &target, options).await?;
+//! for (username, issue) in report.issues() {
+//!     println!("{username}: {issue}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use futures::TryStreamExt;
+
+use crate::{
+    api::user::GetUsersQueryParams,
+    bulk::{run_bulk, BulkOptions, BulkReport},
+    client::MarzbanAPIClient,
+    error::ApiError,
+    models::{
+        proxy::ProxyTypes,
+        units::ByteSize,
+        user::{
+            Inbounds, UserCreate, UserDataLimitResetStrategy, UserModify, UserResponse, UserStatus,
+            UserStatusCreate, UserStatusModify,
+        },
+    },
+};
+
+/// Options for [`migrate_users()`].
+#[derive(Debug, Clone, Default)]
+pub struct MigrationOptions {
+    /// Source inbound tags to target inbound tags. Unmapped tags are kept as is.
+    pub inbound_map: HashMap<String, String>,
+    /// The users to migrate from the source panel. Defaults to all users of the source admin.
+    pub filter: GetUsersQueryParams,
+    /// Overwrite users which already exist on the target panel, instead of skipping them. Defaults to `false`.
+    pub overwrite: bool,
+    /// The concurrency and stop on errors of the migration.
+    pub bulk: BulkOptions,
+}
+
+/// Something which could not be copied for a migrated user.
+#[derive(Debug)]
+pub enum MigrationIssue {
+    /// The protocol has no inbounds on the target panel, so the proxy was dropped.
+    ProtocolUnavailable(ProxyTypes),
+    /// The inbounds, after mapping, do not exist on the target panel and were dropped.
+    /// Without any inbound left, the user gets all inbounds of the protocol.
+    InboundsDropped(Vec<String>),
+    /// The owner admin does not exist on the target panel, or the admins could not be listed.
+    /// The user is owned by the target admin.
+    OwnerMissing(String),
+    /// The data limit is periodically reset, so the full data limit was kept, without the used traffic.
+    UsageNotCopied(ByteSize),
+    /// The owner could not be set.
+    OwnerNotSet(ApiError),
+    /// The user was created, but could not be disabled.
+    NotDisabled(ApiError),
+}
+
+impl std::fmt::Display for MigrationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationIssue::ProtocolUnavailable(protocol) => {
+                write!(f, "no {protocol:?} inbounds on the target, proxy dropped")
+            }
+            MigrationIssue::InboundsDropped(tags) => {
+                write!(f, "inbounds dropped: {}", tags.join(", "))
+            }
+            MigrationIssue::OwnerMissing(admin) => {
+                write!(f, "owner {admin} does not exist on the target")
+            }
+            MigrationIssue::UsageNotCopied(used) => {
+                write!(
+                    f,
+                    "used traffic of {used} not copied, as the data limit resets"
+                )
+            }
+            MigrationIssue::OwnerNotSet(error) => write!(f, "owner not set: {error}"),
+            MigrationIssue::NotDisabled(error) => write!(f, "not disabled: {error}"),
+        }
+    }
+}
+
+/// A user copied to the target panel.
+#[derive(Debug)]
+pub struct MigratedUser {
+    pub user: UserResponse,
+    pub issues: Vec<MigrationIssue>,
+}
+
+/// The outcome of [`migrate_users()`].
+#[derive(Debug)]
+pub struct MigrationReport {
+    /// The created or overwritten users.
+    pub users: BulkReport<MigratedUser>,
+    /// Users which already existed on the target panel.
+    pub skipped: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Whether every user was copied, possibly with issues.
+    pub fn is_success(&self) -> bool {
+        self.users.is_success()
+    }
+
+    /// The issues of the copied users.
+    pub fn issues(&self) -> impl Iterator<Item = (&str, &MigrationIssue)> {
+        self.users.succeeded().flat_map(|(username, migrated)| {
+            migrated.issues.iter().map(move |issue| (username, issue))
+        })
+    }
+}
+
+/// What is known about the target panel.
+struct Target {
+    inbounds: HashMap<ProxyTypes, HashSet<String>>,
+    /// [`None`] if the admins could not be listed.
+    admins: Option<HashSet<String>>,
+    current_admin: String,
+}
+
+enum MigrationAction {
+    Create(Result<UserCreate, validator::ValidationErrors>),
+    Overwrite(UserModify),
+}
+
+struct MigrationItem {
+    action: MigrationAction,
+    disabled: bool,
+    owner: Option<String>,
+    issues: Vec<MigrationIssue>,
+}
+
+impl Target {
+    /// The target inbounds of a protocol, or [`None`] if the protocol is unavailable.
+    fn map_inbounds(
+        &self,
+        protocol: ProxyTypes,
+        tags: &Option<Vec<String>>,
+        inbound_map: &HashMap<String, String>,
+        issues: &mut Vec<MigrationIssue>,
+    ) -> Option<Option<Vec<String>>> {
+        let Some(available) = self.inbounds.get(&protocol).filter(|tags| !tags.is_empty()) else {
+            issues.push(MigrationIssue::ProtocolUnavailable(protocol));
+            return None;
+        };
+        let Some(tags) = tags else {
+            return Some(None);
+        };
+        let mut seen = HashSet::new();
+        let (kept, dropped): (Vec<_>, Vec<_>) = tags
+            .iter()
+            .map(|tag| inbound_map.get(tag).unwrap_or(tag).clone())
+            .filter(|tag| seen.insert(tag.clone()))
+            .partition(|tag| available.contains(tag));
+        if !dropped.is_empty() {
+            issues.push(MigrationIssue::InboundsDropped(dropped));
+        }
+        Some((!kept.is_empty()).then_some(kept))
+    }
+
+    fn item(&self, user: &UserResponse, exists: bool, options: &MigrationOptions) -> MigrationItem {
+        let mut issues = Vec::new();
+        let mut proxies = user.proxies.clone();
+        let mut inbounds = Inbounds::default();
+        let map = &options.inbound_map;
+        if proxies.vmess.is_some() {
+            match self.map_inbounds(ProxyTypes::Vmess, &user.inbounds.vmess, map, &mut issues) {
+                Some(tags) => inbounds.vmess = tags,
+                None => proxies.vmess = None,
+            }
+        }
+        if proxies.vless.is_some() {
+            match self.map_inbounds(ProxyTypes::Vless, &user.inbounds.vless, map, &mut issues) {
+                Some(tags) => inbounds.vless = tags,
+                None => proxies.vless = None,
+            }
+        }
+        if proxies.trojan.is_some() {
+            match self.map_inbounds(ProxyTypes::Trojan, &user.inbounds.trojan, map, &mut issues) {
+                Some(tags) => inbounds.trojan = tags,
+                None => proxies.trojan = None,
+            }
+        }
+        if proxies.shadowsocks.is_some() {
+            let tags = &user.inbounds.shadowsocks;
+            match self.map_inbounds(ProxyTypes::ShadowSocks, tags, map, &mut issues) {
+                Some(tags) => inbounds.shadowsocks = tags,
+                None => proxies.shadowsocks = None,
+            }
+        }
+
+        let data_limit = match user.data_limit {
+            ByteSize::ZERO => ByteSize::ZERO,
+            // Otherwise the reduced data limit would stick after the next reset.
+            limit if user.data_limit_reset_strategy != UserDataLimitResetStrategy::NoReset => {
+                if !user.used_traffic.is_zero() {
+                    issues.push(MigrationIssue::UsageNotCopied(user.used_traffic));
+                }
+                limit
+            }
+            // Not 0, as that is unlimited.
+            limit => limit.saturating_sub(user.used_traffic).max(ByteSize::b(1)),
+        };
+        let on_hold = user.status == UserStatus::OnHold;
+        let disabled = user.status == UserStatus::Disabled;
+        let action = if exists {
+            MigrationAction::Overwrite(UserModify {
+                proxies: Some(proxies),
+                inbounds: Some(inbounds),
+                expire: Some(user.expire),
+                data_limit: Some(data_limit),
+                data_limit_reset_strategy: Some(user.data_limit_reset_strategy),
+                note: user.note.clone(),
+                on_hold_expire_duration: user.on_hold_expire_duration,
+                on_hold_timeout: user.on_hold_timeout,
+                status: Some(match user.status {
+                    UserStatus::Disabled => UserStatusModify::Disabled,
+                    UserStatus::OnHold => UserStatusModify::OnHold,
+                    _ => UserStatusModify::Active,
+                }),
+                ..Default::default()
+            })
+        } else {
+            let mut builder = UserCreate::builder(&user.username)
+                .proxies(proxies)
+                .inbounds(inbounds)
+                .expire(user.expire)
+                .data_limit(data_limit)
+                .data_limit_reset_strategy(user.data_limit_reset_strategy);
+            if let Some(note) = &user.note {
+                builder = builder.note(note);
+            }
+            if on_hold {
+                builder = builder.status(UserStatusCreate::OnHold);
+            }
+            if let Some(duration) = user.on_hold_expire_duration {
+                builder = builder.on_hold_expire_duration(duration);
+            }
+            if let Some(timeout) = user.on_hold_timeout {
+                builder = builder.on_hold_timeout(timeout);
+            }
+            MigrationAction::Create(builder.build())
+        };
+
+        let admin = &user.admin.username;
+        let owner = if *admin == self.current_admin {
+            None
+        } else if self
+            .admins
+            .as_ref()
+            .is_some_and(|admins| admins.contains(admin))
+        {
+            Some(admin.clone())
+        } else {
+            issues.push(MigrationIssue::OwnerMissing(admin.clone()));
+            None
+        };
+
+        MigrationItem {
+            action,
+            // Overwritten users are disabled by the modification.
+            disabled: disabled && !exists,
+            owner,
+            issues,
+        }
+    }
+}
+
+/// Copy users from the `source` panel to the `target` panel, see the [module documentation](self).
+///
+/// Setting the owner admin requires a sudo admin on the target panel.
+pub async fn migrate_users(
+    source: &MarzbanAPIClient,
+    target: &MarzbanAPIClient,
+    options: MigrationOptions,
+) -> Result<MigrationReport, ApiError> {
+    let users: Vec<UserResponse> = source
+        .users_stream(options.filter.clone())
+        .try_collect()
+        .await?;
+    let existing: HashSet<String> = target
+        .users_stream(GetUsersQueryParams::default())
+        .map_ok(|user| user.username)
+        .try_collect()
+        .await?;
+    let admins = match target.get_admins(None, None, None::<String>).await {
+        Ok(admins) => Some(admins.into_iter().map(|admin| admin.username).collect()),
+        Err(ApiError::Forbidden(_)) => None,
+        Err(error) => return Err(error),
+    };
+    let target_state = Target {
+        inbounds: target
+            .get_inbounds()
+            .await?
+            .into_iter()
+            .map(|(protocol, inbounds)| {
+                (
+                    protocol,
+                    inbounds.into_iter().map(|inbound| inbound.tag).collect(),
+                )
+            })
+            .collect(),
+        admins,
+        current_admin: target.get_current_admin().await?.username,
+    };
+
+    let mut skipped = Vec::new();
+    let mut items = Vec::new();
+    for user in &users {
+        let exists = existing.contains(&user.username);
+        if exists && !options.overwrite {
+            skipped.push(user.username.clone());
+            continue;
+        }
+        let item = target_state.item(user, exists, &options);
+        items.push((user.username.clone(), item));
+    }
+
+    let users = run_bulk(items, options.bulk, |username, item| async move {
+        let mut user = match item.action {
+            MigrationAction::Create(user) => target.add_user(user?).await?,
+            MigrationAction::Overwrite(modify) => target.modify_user(&username, modify).await?,
+        };
+        let mut issues = item.issues;
+        if item.disabled {
+            let modify = UserModify {
+                status: Some(UserStatusModify::Disabled),
+                ..Default::default()
+            };
+            match target.modify_user(&username, modify).await {
+                Ok(disabled) => user = disabled,
+                Err(error) => issues.push(MigrationIssue::NotDisabled(error)),
+            }
+        }
+        if let Some(owner) = item.owner {
+            match target.set_owner_of_user(&username, owner).await {
+                Ok(owned) => user = owned,
+                Err(error) => issues.push(MigrationIssue::OwnerNotSet(error)),
+            }
+        }
+        Ok(MigratedUser { user, issues })
+    })
+    .await;
+    Ok(MigrationReport { users, skipped })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_inbounds() {
+        let target = Target {
+            inbounds: HashMap::from([(
+                ProxyTypes::Vless,
+                HashSet::from(["VLESS Reality".to_string(), "VLESS WS".to_string()]),
+            )]),
+            admins: None,
+            current_admin: "admin".to_string(),
+        };
+        let inbound_map = HashMap::from([
+            ("VLESS TCP REALITY".to_string(), "VLESS Reality".to_string()),
+            ("VLESS gRPC".to_string(), "VLESS Reality".to_string()),
+        ]);
+        let tags = ["VLESS TCP REALITY", "VLESS gRPC", "VLESS WS", "VLESS H2"]
+            .map(str::to_string)
+            .to_vec();
+        let mut issues = Vec::new();
+
+        assert_eq!(
+            target.map_inbounds(ProxyTypes::Vless, &Some(tags), &inbound_map, &mut issues),
+            Some(Some(vec![
+                "VLESS Reality".to_string(),
+                "VLESS WS".to_string()
+            ]))
+        );
+        assert_eq!(
+            target.map_inbounds(ProxyTypes::Vless, &None, &inbound_map, &mut issues),
+            Some(None)
+        );
+        assert_eq!(
+            target.map_inbounds(ProxyTypes::Trojan, &None, &inbound_map, &mut issues),
+            None
+        );
+        assert!(matches!(
+            &issues[..],
+            [
+                MigrationIssue::InboundsDropped(dropped),
+                MigrationIssue::ProtocolUnavailable(ProxyTypes::Trojan),
+            ] if dropped == &["VLESS H2"]
+        ));
+    }
+}
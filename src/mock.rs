@@ -0,0 +1,1954 @@
+//! # Mock module
+//!
+//! This module contains an in-process fake Marzban server, to test code using the client without a real panel.
+//!
+//! Only available with the `mock` feature.
+//!
+//! The server keeps admins, users, nodes, user templates, hosts, the core config and logs in memory, issues tokens,
+//! serves the logs websockets, and answers with the same JSON shapes and `401`/`403`/`404`/`409`/`422` responses as Marzban.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use marzban_api::mock::MockServer;
+//! use marzban_api::models::user::{UserCreate, Vless};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let server = MockServer::start().await;
+//!     let client = server.authenticated_client().await;
+//!
+//!     let user = UserCreate::builder("alice").vless(Vless::default()).build().unwrap();
+//!     client.add_user(user).await.unwrap();
+//! }
+//! ```
+
+// Handlers return early with `Response`s, as is idiomatic for axum.
+#![allow(clippy::result_large_err)]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Form, Path, RawQuery, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+use tokio::{net::TcpListener, sync::broadcast, task::JoinHandle};
+
+use crate::{client::MarzbanAPIClient, models::auth::BodyAdminTokenApiAdminTokenPost};
+
+/// Username of the sudo admin every mock server starts with.
+pub const MOCK_ADMIN_USERNAME: &str = "admin";
+/// Password of the sudo admin every mock server starts with.
+pub const MOCK_ADMIN_PASSWORD: &str = "admin";
+
+/// Address used in the generated links and subscriptions.
+const MOCK_HOST: &str = "mock.local";
+
+type Shared = Arc<Mutex<MockState>>;
+type MockResult = Result<Response, Response>;
+
+/// An in-process fake Marzban server, listening on a random local port.
+///
+/// The server is stopped when dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Shared,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Start a new server with a sudo admin ([`MOCK_ADMIN_USERNAME`]/[`MOCK_ADMIN_PASSWORD`])
+    /// and one inbound per protocol.
+    pub async fn start() -> MockServer {
+        let state: Shared = Arc::new(Mutex::new(MockState::new()));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind mock server");
+        let addr = listener.local_addr().expect("Failed to get mock address");
+        let app = router(state.clone());
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        MockServer {
+            addr,
+            state,
+            handle,
+        }
+    }
+
+    /// The base URL of the server, e.g. `http://127.0.0.1:12345`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A new unauthenticated client pointed at the server.
+    pub fn client(&self) -> MarzbanAPIClient {
+        MarzbanAPIClient::new(&self.url())
+    }
+
+    /// A new client pointed at the server, authenticated as the default sudo admin.
+    pub async fn authenticated_client(&self) -> MarzbanAPIClient {
+        let client = self.client();
+        client
+            .authenticate(Self::admin_auth(MOCK_ADMIN_USERNAME, MOCK_ADMIN_PASSWORD))
+            .await
+            .expect("Failed to authenticate against the mock server");
+        client
+    }
+
+    /// Credentials for the token endpoint.
+    pub fn admin_auth(username: &str, password: &str) -> BodyAdminTokenApiAdminTokenPost {
+        BodyAdminTokenApiAdminTokenPost {
+            grant_type: Some("password".to_string()),
+            username: username.to_string(),
+            password: password.to_string(),
+            scope: "".to_string(),
+            client_id: None,
+            client_secret: None,
+        }
+    }
+
+    /// Add an admin directly, without going through the API.
+    pub fn add_admin(&self, username: &str, password: &str, is_sudo: bool) {
+        self.state().admins.insert(
+            username.to_string(),
+            MockAdmin::new(username, password, is_sudo),
+        );
+    }
+
+    /// Invalidate all issued tokens, as if they had expired.
+    pub fn expire_tokens(&self) {
+        self.state().tokens.clear();
+    }
+
+    /// Answer the next `count` requests with the given status code, before handling them.
+    pub fn fail_next(&self, status: StatusCode, count: usize) {
+        self.state()
+            .failures
+            .extend(std::iter::repeat_n(status, count));
+    }
+
+    /// The number of requests received so far.
+    pub fn request_count(&self) -> usize {
+        self.state().requests
+    }
+
+    /// Set the used traffic of a user, in bytes.
+    pub fn set_used_traffic(&self, username: &str, used_traffic: u64) {
+        let mut state = self.state();
+        if let Some(user) = state.users.get_mut(username) {
+            user["used_traffic"] = json!(used_traffic);
+            user["lifetime_used_traffic"] = json!(used_traffic);
+        }
+    }
+
+    /// Log a line to the core logs websocket.
+    pub fn push_core_log(&self, line: impl Into<String>) {
+        self.state().core_logs.push(line.into());
+    }
+
+    /// Log a line to the logs websocket of a node.
+    pub fn push_node_log(&self, node_id: u64, line: impl Into<String>) {
+        self.state()
+            .node_logs
+            .entry(node_id)
+            .or_default()
+            .push(line.into());
+    }
+
+    /// Drop all open logs websockets, without a close frame.
+    pub fn disconnect_logs(&self) {
+        let state = self.state();
+        for logs in std::iter::once(&state.core_logs).chain(state.node_logs.values()) {
+            let _ = logs.sender.send(LogEvent::Disconnect);
+        }
+    }
+
+    /// The subscription token of a user.
+    pub fn subscription_token(&self, username: &str) -> Option<String> {
+        self.state()
+            .users
+            .get(username)
+            .map(|user| user["subscription_url"].as_str().unwrap_or_default())
+            .and_then(|url| url.strip_prefix("/sub/").map(str::to_owned))
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().expect("Mock state poisoned")
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockAdmin {
+    username: String,
+    password: String,
+    is_sudo: bool,
+    telegram_id: Option<u64>,
+    discord_webhook: Option<String>,
+}
+
+impl MockAdmin {
+    fn new(username: &str, password: &str, is_sudo: bool) -> Self {
+        MockAdmin {
+            username: username.to_string(),
+            password: password.to_string(),
+            is_sudo,
+            telegram_id: None,
+            discord_webhook: None,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "username": self.username,
+            "is_sudo": self.is_sudo,
+            "telegram_id": self.telegram_id,
+            "discord_webhook": self.discord_webhook,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct MockState {
+    admins: BTreeMap<String, MockAdmin>,
+    tokens: HashMap<String, String>,
+    users: BTreeMap<String, Value>,
+    nodes: BTreeMap<u64, Value>,
+    next_node_id: u64,
+    templates: BTreeMap<u64, Value>,
+    next_template_id: u64,
+    hosts: Map<String, Value>,
+    core_config: Value,
+    core_logs: MockLogs,
+    node_logs: BTreeMap<u64, MockLogs>,
+    failures: Vec<StatusCode>,
+    requests: usize,
+}
+
+#[derive(Debug, Clone)]
+enum LogEvent {
+    Line(String),
+    Disconnect,
+}
+
+/// The lines logged so far, which are replayed on connect, and the channel to the open websockets.
+#[derive(Debug)]
+struct MockLogs {
+    history: Vec<String>,
+    sender: broadcast::Sender<LogEvent>,
+}
+
+impl Default for MockLogs {
+    fn default() -> Self {
+        MockLogs {
+            history: Vec::new(),
+            sender: broadcast::channel(256).0,
+        }
+    }
+}
+
+impl MockLogs {
+    fn push(&mut self, line: String) {
+        self.history.push(line.clone());
+        let _ = self.sender.send(LogEvent::Line(line));
+    }
+}
+
+/// The inbounds of the mock core: `(tag, protocol, network, tls, port)`.
+const INBOUNDS: [(&str, &str, &str, &str, u16); 4] = [
+    ("VMess TCP", "vmess", "tcp", "none", 8080),
+    ("VLESS TCP REALITY", "vless", "tcp", "reality", 443),
+    ("Trojan Websocket TLS", "trojan", "ws", "tls", 2083),
+    ("Shadowsocks TCP", "shadowsocks", "tcp", "none", 1080),
+];
+
+impl MockState {
+    fn new() -> Self {
+        let mut admins = BTreeMap::new();
+        admins.insert(
+            MOCK_ADMIN_USERNAME.to_string(),
+            MockAdmin::new(MOCK_ADMIN_USERNAME, MOCK_ADMIN_PASSWORD, true),
+        );
+        let hosts = INBOUNDS
+            .iter()
+            .map(|(tag, ..)| {
+                (
+                    tag.to_string(),
+                    json!([{
+                        "remark": "🚀 {USERNAME}",
+                        "address": MOCK_HOST,
+                        "port": null,
+                        "sni": null,
+                        "host": null,
+                        "path": null,
+                        "security": "inbound_default",
+                        "alpn": null,
+                        "fingerprint": null,
+                        "allow_insecure": false,
+                        "is_disabled": false,
+                        "mux_enable": false,
+                        "fragment_settings": null,
+                        "noise_setting": null,
+                        "random_user_agent": false,
+                    }]),
+                )
+            })
+            .collect();
+        MockState {
+            admins,
+            tokens: HashMap::new(),
+            users: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+            next_node_id: 1,
+            templates: BTreeMap::new(),
+            next_template_id: 1,
+            hosts,
+            core_config: default_core_config(),
+            core_logs: MockLogs::default(),
+            node_logs: BTreeMap::new(),
+            failures: Vec::new(),
+            requests: 0,
+        }
+    }
+
+    fn authorize(&self, headers: &HeaderMap) -> Result<MockAdmin, Response> {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| self.tokens.get(token))
+            .and_then(|username| self.admins.get(username))
+            .cloned()
+            .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Could not validate credentials"))
+    }
+
+    fn authorize_sudo(&self, headers: &HeaderMap) -> Result<MockAdmin, Response> {
+        let admin = self.authorize(headers)?;
+        if !admin.is_sudo {
+            return Err(error(StatusCode::FORBIDDEN, "You're not allowed"));
+        }
+        Ok(admin)
+    }
+
+    /// The user, if it exists and the admin is allowed to manage it.
+    fn owned_user(&mut self, admin: &MockAdmin, username: &str) -> Result<&mut Value, Response> {
+        let user = self
+            .users
+            .get_mut(username)
+            .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+        if !admin.is_sudo && user["admin"]["username"] != admin.username.as_str() {
+            return Err(error(StatusCode::FORBIDDEN, "You're not allowed"));
+        }
+        Ok(user)
+    }
+
+    /// Authorize a logs websocket from its `token` and `interval` query parameters.
+    ///
+    /// Marzban closes the websocket before accepting it, which the server turns into a `403 Forbidden` handshake response.
+    fn authorize_logs(&self, query: Option<String>) -> Result<Option<Duration>, Response> {
+        let pairs = query_pairs(query);
+        let rejected = || StatusCode::FORBIDDEN.into_response();
+        let admin = query_value(&pairs, "token")
+            .and_then(|token| self.tokens.get(&token))
+            .and_then(|username| self.admins.get(username))
+            .ok_or_else(rejected)?;
+        if !admin.is_sudo {
+            return Err(rejected());
+        }
+        query_value(&pairs, "interval")
+            .map(|interval| {
+                interval
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|interval| *interval > 0.0 && *interval <= 10.0)
+                    .map(Duration::from_secs_f64)
+                    .ok_or_else(rejected)
+            })
+            .transpose()
+    }
+
+    fn user_by_token(&self, token: &str) -> Result<&Value, Response> {
+        let subscription_url = format!("/sub/{token}");
+        self.users
+            .values()
+            .find(|user| user["subscription_url"] == subscription_url.as_str())
+            .ok_or_else(|| error(StatusCode::NOT_FOUND, "Not Found"))
+    }
+}
+
+fn router(state: Shared) -> Router {
+    Router::new()
+        .route("/", get(base))
+        .route("/api/admin/token", post(admin_token))
+        .route("/api/admin", get(get_current_admin).post(create_admin))
+        .route(
+            "/api/admin/{username}",
+            put(modify_admin).delete(delete_admin),
+        )
+        .route("/api/admins", get(get_admins))
+        .route("/api/core", get(get_core_stats))
+        .route("/api/core/restart", post(restart_core))
+        .route("/api/core/logs", get(core_logs))
+        .route(
+            "/api/core/config",
+            get(get_core_config).put(modify_core_config),
+        )
+        .route("/api/node/settings", get(get_node_settings))
+        .route("/api/node", post(add_node))
+        .route(
+            "/api/node/{id}",
+            get(get_node).put(modify_node).delete(remove_node),
+        )
+        .route("/api/node/{id}/reconnect", post(reconnect_node))
+        .route("/api/node/{id}/logs", get(node_logs))
+        .route("/api/nodes", get(get_nodes))
+        .route("/api/nodes/usage", get(get_nodes_usage))
+        .route("/api/system", get(get_system_stats))
+        .route("/api/inbounds", get(get_inbounds))
+        .route("/api/hosts", get(get_hosts).put(modify_hosts))
+        .route("/api/user", post(add_user))
+        .route(
+            "/api/user/{username}",
+            get(get_user).put(modify_user).delete(delete_user),
+        )
+        .route("/api/user/{username}/reset", post(reset_user_data_usage))
+        .route(
+            "/api/user/{username}/revoke_sub",
+            post(revoke_user_subscription),
+        )
+        .route("/api/user/{username}/usage", get(get_user_usage))
+        .route("/api/user/{username}/set-owner", put(set_owner))
+        .route("/api/users", get(get_users))
+        .route("/api/users/reset", post(reset_all_users_data_usage))
+        .route("/api/users/usage", get(get_all_users_usage))
+        .route(
+            "/api/users/expired",
+            get(get_expired_users).delete(delete_expired_users),
+        )
+        .route(
+            "/api/user_template",
+            get(get_user_templates).post(add_user_template),
+        )
+        .route(
+            "/api/user_template/{id}",
+            get(get_user_template)
+                .put(modify_user_template)
+                .delete(remove_user_template),
+        )
+        .route("/sub/{token}", get(user_subscription))
+        .route("/sub/{token}/info", get(user_subscription_info))
+        .route("/sub/{token}/usage", get(user_subscription_usage))
+        .route(
+            "/sub/{token}/{client_type}",
+            get(user_subscription_with_client_type),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            count_and_fail,
+        ))
+        .with_state(state)
+}
+
+/// Counts requests, and answers with injected failures from [`MockServer::fail_next()`].
+async fn count_and_fail(State(state): State<Shared>, request: Request, next: Next) -> Response {
+    let failure = {
+        let mut state = lock(&state);
+        state.requests += 1;
+        (!state.failures.is_empty()).then(|| state.failures.remove(0))
+    };
+    match failure {
+        Some(status) => error(status, "Injected failure"),
+        None => next.run(request).await,
+    }
+}
+
+fn lock(state: &Shared) -> MutexGuard<'_, MockState> {
+    state.lock().expect("Mock state poisoned")
+}
+
+fn error(status: StatusCode, detail: &str) -> Response {
+    (status, Json(json!({ "detail": detail }))).into_response()
+}
+
+/// A FastAPI style `422 Unprocessable Entity` response.
+fn validation_error(loc: Value, msg: &str) -> Response {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(json!({ "detail": [{ "loc": loc, "msg": msg, "type": "value_error" }] })),
+    )
+        .into_response()
+}
+
+fn ok(value: Value) -> MockResult {
+    Ok(Json(value).into_response())
+}
+
+fn now() -> String {
+    naive(Utc::now())
+}
+
+fn naive(date: DateTime<Utc>) -> String {
+    date.naive_utc().format("%Y-%m-%dT%H:%M:%S%.6f").to_string()
+}
+
+fn query_pairs(query: Option<String>) -> Vec<(String, String)> {
+    serde_urlencoded::from_str(query.as_deref().unwrap_or_default()).unwrap_or_default()
+}
+
+fn query_value(pairs: &[(String, String)], key: &str) -> Option<String> {
+    pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+}
+
+fn query_values(pairs: &[(String, String)], key: &str) -> Vec<String> {
+    pairs
+        .iter()
+        .filter(|(k, _)| k == key)
+        .map(|(_, v)| v.clone())
+        .collect()
+}
+
+fn paginate<T>(items: Vec<T>, pairs: &[(String, String)]) -> Vec<T> {
+    let offset = query_value(pairs, "offset")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let limit = query_value(pairs, "limit")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(usize::MAX);
+    items.into_iter().skip(offset).take(limit).collect()
+}
+
+fn random_uuid() -> String {
+    let hex: String = (0..32)
+        .map(|_| char::from_digit(fastrand::u32(0..16), 16).unwrap())
+        .collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn random_password() -> String {
+    fastrand::choose_multiple(
+        "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789".chars(),
+        22,
+    )
+    .into_iter()
+    .collect()
+}
+
+/// A subscription token in Marzban's format: the base64 encoded `username,created_at` followed by a signature.
+///
+/// The signature is random rather than derived from a secret, so revoking always yields a new token.
+fn subscription_token(username: &str) -> String {
+    let data = URL_SAFE_NO_PAD.encode(format!("{username},{}", Utc::now().timestamp()));
+    let signature: String = (0..10).map(|_| fastrand::alphanumeric()).collect();
+    format!("{data}{signature}")
+}
+
+fn encode_remark(remark: &str) -> String {
+    remark
+        .replace('%', "%25")
+        .replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+        .replace('#', "%23")
+}
+
+fn valid_username(username: &str) -> bool {
+    (3..=32).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn inbound_tags(protocol: &str) -> Vec<String> {
+    INBOUNDS
+        .iter()
+        .filter(|(_, p, ..)| *p == protocol)
+        .map(|(tag, ..)| tag.to_string())
+        .collect()
+}
+
+fn inbound(tag: &str) -> Option<(&'static str, &'static str, &'static str, &'static str, u16)> {
+    INBOUNDS.iter().find(|(t, ..)| *t == tag).copied()
+}
+
+/// Fill in generated credentials for the enabled proxies, like Marzban does.
+fn fill_proxies(proxies: &Value) -> Result<Value, Response> {
+    let Some(proxies) = proxies.as_object().filter(|p| !p.is_empty()) else {
+        return Err(validation_error(
+            json!(["body", "proxies"]),
+            "Each user needs at least one proxy",
+        ));
+    };
+    let mut filled = Map::new();
+    for (protocol, settings) in proxies {
+        let mut settings = settings.as_object().cloned().unwrap_or_default();
+        let missing = |settings: &Map<String, Value>, key: &str| {
+            settings.get(key).is_none_or(|value| value.is_null())
+        };
+        match protocol.as_str() {
+            "vmess" | "vless" => {
+                if missing(&settings, "id") {
+                    settings.insert("id".to_string(), json!(random_uuid()));
+                }
+                if protocol == "vless" && missing(&settings, "flow") {
+                    settings.insert("flow".to_string(), json!(""));
+                }
+            }
+            "trojan" | "shadowsocks" => {
+                if missing(&settings, "password") {
+                    settings.insert("password".to_string(), json!(random_password()));
+                }
+                if protocol == "trojan" && missing(&settings, "flow") {
+                    settings.insert("flow".to_string(), json!(""));
+                }
+                if protocol == "shadowsocks" && missing(&settings, "method") {
+                    settings.insert("method".to_string(), json!("chacha20-ietf-poly1305"));
+                }
+            }
+            _ => {
+                return Err(validation_error(
+                    json!(["body", "proxies", protocol]),
+                    "Unknown proxy type",
+                ))
+            }
+        }
+        filled.insert(protocol.clone(), Value::Object(settings));
+    }
+    Ok(Value::Object(filled))
+}
+
+/// The inbounds of the enabled proxies, defaulting to all inbounds of a protocol.
+fn resolve_inbounds(proxies: &Value, inbounds: Option<&Value>) -> Result<Value, Response> {
+    let mut resolved = Map::new();
+    for protocol in proxies.as_object().into_iter().flat_map(|p| p.keys()) {
+        let tags: Vec<String> = match inbounds.and_then(|i| i.get(protocol)) {
+            Some(Value::Array(tags)) if !tags.is_empty() => tags
+                .iter()
+                .filter_map(|tag| tag.as_str().map(str::to_owned))
+                .collect(),
+            _ => inbound_tags(protocol),
+        };
+        for tag in &tags {
+            if inbound(tag).is_none_or(|(_, p, ..)| p != protocol) {
+                return Err(error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Inbound {tag} doesn't exist"),
+                ));
+            }
+        }
+        resolved.insert(protocol.clone(), json!(tags));
+    }
+    Ok(Value::Object(resolved))
+}
+
+/// Recompute the derived fields of a user: links and excluded inbounds.
+fn refresh_user(user: &mut Value) {
+    let username = user["username"].as_str().unwrap_or_default().to_string();
+    let mut links = Vec::new();
+    let mut excluded = Map::new();
+    for (protocol, settings) in user["proxies"].as_object().cloned().unwrap_or_default() {
+        let tags: Vec<String> = user["inbounds"][&protocol]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|tag| tag.as_str().map(str::to_owned))
+            .collect();
+        excluded.insert(
+            protocol.clone(),
+            json!(inbound_tags(&protocol)
+                .into_iter()
+                .filter(|tag| !tags.contains(tag))
+                .collect::<Vec<_>>()),
+        );
+        for tag in tags {
+            if let Some(inbound) = inbound(&tag) {
+                links.push(share_link(
+                    inbound,
+                    &settings,
+                    &format!("{tag} ({username})"),
+                ));
+            }
+        }
+    }
+    user["links"] = json!(links);
+    user["excluded_inbounds"] = Value::Object(excluded);
+}
+
+fn share_link(
+    (_, protocol, network, tls, port): (&str, &str, &str, &str, u16),
+    settings: &Value,
+    remark: &str,
+) -> String {
+    let str_setting = |key: &str| settings[key].as_str().unwrap_or_default().to_string();
+    match protocol {
+        "vmess" => {
+            let config = json!({
+                "add": MOCK_HOST, "aid": "0", "host": "", "id": str_setting("id"), "net": network,
+                "path": "", "port": port.to_string(), "ps": remark, "scy": "auto", "tls": tls,
+                "type": "none", "v": "2",
+            });
+            format!("vmess://{}", STANDARD.encode(config.to_string()))
+        }
+        "vless" => format!(
+            "vless://{}@{MOCK_HOST}:{port}?security={tls}&type={network}&headerType=none&sni=www.example.com&fp=chrome&pbk=mockpublickey&sid=6ba85179e30d4fc2#{}",
+            str_setting("id"),
+            encode_remark(remark)
+        ),
+        "trojan" => format!(
+            "trojan://{}@{MOCK_HOST}:{port}?security={tls}&type={network}&host={MOCK_HOST}&path=%2Ftrojan&sni={MOCK_HOST}&alpn=h2%2Chttp%2F1.1#{}",
+            str_setting("password"),
+            encode_remark(remark)
+        ),
+        _ => format!(
+            "ss://{}@{MOCK_HOST}:{port}#{}",
+            STANDARD.encode(format!("{}:{}", str_setting("method"), str_setting("password"))),
+            encode_remark(remark)
+        ),
+    }
+}
+
+fn apply_user_fields(user: &mut Value, body: &Map<String, Value>) {
+    for key in [
+        "expire",
+        "data_limit",
+        "data_limit_reset_strategy",
+        "note",
+        "on_hold_expire_duration",
+        "on_hold_timeout",
+        "auto_delete_in_days",
+    ] {
+        if let Some(value) = body.get(key).filter(|value| !value.is_null()) {
+            user[key] = value.clone();
+        }
+    }
+}
+
+async fn base() -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/html")],
+        "<html><body>Marzban</body></html>",
+    )
+        .into_response()
+}
+
+async fn admin_token(
+    State(state): State<Shared>,
+    Form(form): Form<HashMap<String, String>>,
+) -> MockResult {
+    let (Some(username), Some(password)) = (form.get("username"), form.get("password")) else {
+        return Err(validation_error(
+            json!(["body", "username"]),
+            "field required",
+        ));
+    };
+    let mut state = lock(&state);
+    match state.admins.get(username) {
+        Some(admin) if &admin.password == password => {
+            let token = format!("mock-{:032x}", fastrand::u128(..));
+            state.tokens.insert(token.clone(), username.clone());
+            ok(json!({ "access_token": token, "token_type": "bearer" }))
+        }
+        _ => Err(error(
+            StatusCode::UNAUTHORIZED,
+            "Incorrect username or password",
+        )),
+    }
+}
+
+async fn get_current_admin(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    ok(lock(&state).authorize(&headers)?.to_json())
+}
+
+async fn create_admin(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let username = body["username"].as_str().unwrap_or_default();
+    if state.admins.contains_key(username) {
+        return Err(error(StatusCode::CONFLICT, "Admin already exists"));
+    }
+    let mut admin = MockAdmin::new(
+        username,
+        body["password"].as_str().unwrap_or_default(),
+        body["is_sudo"].as_bool().unwrap_or_default(),
+    );
+    admin.telegram_id = body["telegram_id"].as_u64();
+    admin.discord_webhook = body["discord_webhook"].as_str().map(str::to_owned);
+    let response = admin.to_json();
+    state.admins.insert(username.to_string(), admin);
+    ok(response)
+}
+
+async fn modify_admin(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+    Json(body): Json<Value>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let admin = state
+        .admins
+        .get_mut(&username)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Admin not found"))?;
+    if let Some(password) = body["password"].as_str() {
+        admin.password = password.to_string();
+    }
+    admin.is_sudo = body["is_sudo"].as_bool().unwrap_or(admin.is_sudo);
+    admin.telegram_id = body["telegram_id"].as_u64();
+    admin.discord_webhook = body["discord_webhook"].as_str().map(str::to_owned);
+    ok(admin.to_json())
+}
+
+async fn delete_admin(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    state
+        .admins
+        .remove(&username)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Admin not found"))?;
+    ok(json!({}))
+}
+
+async fn get_admins(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> MockResult {
+    let state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let pairs = query_pairs(query);
+    let username = query_value(&pairs, "username");
+    let admins = state
+        .admins
+        .values()
+        .filter(|admin| {
+            username
+                .as_ref()
+                .is_none_or(|username| admin.username.contains(username.as_str()))
+        })
+        .map(MockAdmin::to_json)
+        .collect();
+    ok(Value::Array(paginate(admins, &pairs)))
+}
+
+async fn get_core_stats(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    lock(&state).authorize(&headers)?;
+    ok(json!({ "version": "1.8.24", "started": true, "logs_websocket": "/api/core/logs" }))
+}
+
+async fn restart_core(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    lock(&state).authorize_sudo(&headers)?;
+    ok(json!({}))
+}
+
+async fn core_logs(
+    State(state): State<Shared>,
+    RawQuery(query): RawQuery,
+    upgrade: WebSocketUpgrade,
+) -> MockResult {
+    let (history, receiver, interval) = {
+        let state = lock(&state);
+        let interval = state.authorize_logs(query)?;
+        let logs = &state.core_logs;
+        (logs.history.clone(), logs.sender.subscribe(), interval)
+    };
+    Ok(upgrade.on_upgrade(move |socket| send_logs(socket, history, receiver, interval)))
+}
+
+/// Replay the history, then forward new lines until disconnected, batched every `interval` if given.
+async fn send_logs(
+    mut socket: WebSocket,
+    history: Vec<String>,
+    mut receiver: broadcast::Receiver<LogEvent>,
+    interval: Option<Duration>,
+) {
+    let Some(interval) = interval else {
+        for line in history {
+            if socket.send(Message::Text(line.into())).await.is_err() {
+                return;
+            }
+        }
+        while let Ok(LogEvent::Line(line)) = receiver.recv().await {
+            if socket.send(Message::Text(line.into())).await.is_err() {
+                return;
+            }
+        }
+        return;
+    };
+
+    let mut batch = history;
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                if !batch.is_empty() {
+                    let message = Message::Text(batch.join("\n").into());
+                    batch.clear();
+                    if socket.send(message).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            event = receiver.recv() => match event {
+                Ok(LogEvent::Line(line)) => batch.push(line),
+                _ => return,
+            },
+        }
+    }
+}
+
+async fn get_core_config(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    let state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    ok(state.core_config.clone())
+}
+
+async fn modify_core_config(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    if !body.is_object() {
+        return Err(validation_error(
+            json!(["body"]),
+            "value is not a valid dict",
+        ));
+    }
+    state.core_config = body.clone();
+    ok(body)
+}
+
+async fn get_node_settings(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    lock(&state).authorize_sudo(&headers)?;
+    ok(json!({
+        "min_node_version": "v0.2.0",
+        "certificate": "-----BEGIN CERTIFICATE-----\nMOCK\n-----END CERTIFICATE-----\n",
+    }))
+}
+
+fn node_id(id: &str) -> Result<u64, Response> {
+    id.parse()
+        .map_err(|_| validation_error(json!(["path", "node_id"]), "value is not a valid integer"))
+}
+
+async fn add_node(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let name = body["name"].as_str().unwrap_or_default();
+    if state.nodes.values().any(|node| node["name"] == name) {
+        return Err(error(
+            StatusCode::CONFLICT,
+            &format!("Node \"{name}\" already exists"),
+        ));
+    }
+    let id = state.next_node_id;
+    state.next_node_id += 1;
+    let node = json!({
+        "name": name,
+        "address": body["address"],
+        "port": body["port"].as_u64().unwrap_or(62050),
+        "api_port": body["api_port"].as_u64().unwrap_or(62051),
+        "usage_coefficient": body["usage_coefficient"].as_f64().unwrap_or(1.0),
+        "id": id,
+        "xray_version": "1.8.24",
+        "status": "connected",
+        "message": null,
+    });
+    state.nodes.insert(id, node.clone());
+    ok(node)
+}
+
+async fn get_node(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> MockResult {
+    let state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let node = state
+        .nodes
+        .get(&node_id(&id)?)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Node not found"))?;
+    ok(node.clone())
+}
+
+async fn modify_node(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(body): Json<Value>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let node = state
+        .nodes
+        .get_mut(&node_id(&id)?)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Node not found"))?;
+    for key in [
+        "name",
+        "address",
+        "port",
+        "api_port",
+        "usage_coefficient",
+        "status",
+    ] {
+        if let Some(value) = body.get(key).filter(|value| !value.is_null()) {
+            node[key] = value.clone();
+        }
+    }
+    ok(node.clone())
+}
+
+async fn remove_node(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    state
+        .nodes
+        .remove(&node_id(&id)?)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Node not found"))?;
+    ok(json!({}))
+}
+
+async fn reconnect_node(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> MockResult {
+    let state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    state
+        .nodes
+        .get(&node_id(&id)?)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Node not found"))?;
+    ok(json!({}))
+}
+
+async fn node_logs(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+    upgrade: WebSocketUpgrade,
+) -> MockResult {
+    let (history, receiver, interval) = {
+        let mut state = lock(&state);
+        let interval = state.authorize_logs(query)?;
+        let id = node_id(&id)?;
+        if !state.nodes.contains_key(&id) {
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+        let logs = state.node_logs.entry(id).or_default();
+        (logs.history.clone(), logs.sender.subscribe(), interval)
+    };
+    Ok(upgrade.on_upgrade(move |socket| send_logs(socket, history, receiver, interval)))
+}
+
+async fn get_nodes(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    let state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    ok(Value::Array(state.nodes.values().cloned().collect()))
+}
+
+async fn get_nodes_usage(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    let state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let mut usages =
+        vec![json!({ "node_id": null, "node_name": "Master", "uplink": 0, "downlink": 0 })];
+    usages.extend(state.nodes.values().map(|node| {
+        json!({ "node_id": node["id"], "node_name": node["name"], "uplink": 0, "downlink": 0 })
+    }));
+    ok(json!({ "usages": usages }))
+}
+
+async fn get_system_stats(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    let state = lock(&state);
+    state.authorize(&headers)?;
+    let active = state
+        .users
+        .values()
+        .filter(|user| user["status"] == "active")
+        .count();
+    ok(json!({
+        "version": "0.7.0",
+        "mem_total": 4_294_967_296u64,
+        "mem_used": 1_073_741_824u64,
+        "cpu_cores": 2,
+        "cpu_usage": 12.5,
+        "total_user": state.users.len(),
+        "users_active": active,
+        "incoming_bandwidth": 0,
+        "outgoing_bandwidth": 0,
+        "incoming_bandwidth_speed": 0,
+        "outgoing_bandwidth_speed": 0,
+    }))
+}
+
+async fn get_inbounds(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    lock(&state).authorize(&headers)?;
+    let mut inbounds = Map::new();
+    for (tag, protocol, network, tls, port) in INBOUNDS {
+        inbounds.insert(
+            protocol.to_string(),
+            json!([{ "tag": tag, "protocol": protocol, "network": network, "tls": tls, "port": port }]),
+        );
+    }
+    ok(Value::Object(inbounds))
+}
+
+async fn get_hosts(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    let state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    ok(Value::Object(state.hosts.clone()))
+}
+
+async fn modify_hosts(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Json(body): Json<Map<String, Value>>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    for tag in body.keys() {
+        if inbound(tag).is_none() {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                &format!("Inbound {tag} doesn't exist"),
+            ));
+        }
+    }
+    state.hosts.extend(body);
+    ok(Value::Object(state.hosts.clone()))
+}
+
+async fn add_user(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Json(body): Json<Map<String, Value>>,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    let username = body
+        .get("username")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    if !valid_username(username) {
+        return Err(validation_error(
+            json!(["body", "username"]),
+            "Username only can be 3 to 32 characters and contain a-z, 0-9, and underscores in between.",
+        ));
+    }
+    if state.users.contains_key(username) {
+        return Err(error(StatusCode::CONFLICT, "User already exists"));
+    }
+
+    let proxies = fill_proxies(body.get("proxies").unwrap_or(&Value::Null))?;
+    let inbounds = resolve_inbounds(&proxies, body.get("inbounds"))?;
+    let status = body
+        .get("status")
+        .and_then(Value::as_str)
+        .unwrap_or("active");
+    let mut user = json!({
+        "proxies": proxies,
+        "expire": null,
+        "data_limit": null,
+        "data_limit_reset_strategy": "no_reset",
+        "inbounds": inbounds,
+        "note": null,
+        "sub_updated_at": null,
+        "sub_last_user_agent": null,
+        "online_at": null,
+        "on_hold_expire_duration": null,
+        "on_hold_timeout": null,
+        "auto_delete_in_days": null,
+        "username": username,
+        "status": status,
+        "used_traffic": 0,
+        "lifetime_used_traffic": 0,
+        "created_at": now(),
+        "links": [],
+        "subscription_url": format!("/sub/{}", subscription_token(username)),
+        "excluded_inbounds": {},
+        "admin": admin.to_json(),
+    });
+    apply_user_fields(&mut user, &body);
+    refresh_user(&mut user);
+    state.users.insert(username.to_string(), user.clone());
+    ok(user)
+}
+
+async fn get_user(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    ok(state.owned_user(&admin, &username)?.clone())
+}
+
+async fn modify_user(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+    Json(body): Json<Map<String, Value>>,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    let user = state.owned_user(&admin, &username)?;
+    if let Some(proxies) = body.get("proxies").filter(|p| !p.is_null()) {
+        user["proxies"] = fill_proxies(proxies)?;
+        user["inbounds"] = resolve_inbounds(&user["proxies"], None)?;
+    }
+    if let Some(inbounds) = body.get("inbounds").filter(|i| !i.is_null()) {
+        user["inbounds"] = resolve_inbounds(&user["proxies"], Some(inbounds))?;
+    }
+    if let Some(status) = body.get("status").filter(|s| !s.is_null()) {
+        user["status"] = status.clone();
+    }
+    apply_user_fields(user, &body);
+    refresh_user(user);
+    ok(user.clone())
+}
+
+async fn delete_user(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    state.owned_user(&admin, &username)?;
+    state.users.remove(&username);
+    ok(json!({}))
+}
+
+async fn reset_user_data_usage(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    let user = state.owned_user(&admin, &username)?;
+    user["used_traffic"] = json!(0);
+    if user["status"] == "limited" {
+        user["status"] = json!("active");
+    }
+    ok(user.clone())
+}
+
+async fn revoke_user_subscription(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    let user = state.owned_user(&admin, &username)?;
+    let proxies = user["proxies"].as_object().cloned().unwrap_or_default();
+    user["proxies"] = fill_proxies(&Value::Object(
+        proxies
+            .keys()
+            .map(|protocol| (protocol.clone(), json!({})))
+            .collect(),
+    ))?;
+    user["subscription_url"] = json!(format!("/sub/{}", subscription_token(&username)));
+    refresh_user(user);
+    ok(user.clone())
+}
+
+async fn get_user_usage(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    let user = state.owned_user(&admin, &username)?;
+    ok(json!({
+        "username": username,
+        "usages": [{ "node_id": null, "node_name": "Master", "used_traffic": user["used_traffic"] }],
+    }))
+}
+
+async fn set_owner(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(username): Path<String>,
+    RawQuery(query): RawQuery,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize_sudo(&headers)?;
+    let owner = query_value(&query_pairs(query), "admin_username").unwrap_or_default();
+    let owner = state
+        .admins
+        .get(&owner)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Admin not found"))?
+        .to_json();
+    let user = state.owned_user(&admin, &username)?;
+    user["admin"] = owner;
+    ok(user.clone())
+}
+
+fn sort_key(user: &Value, field: &str) -> (u64, String) {
+    match field {
+        "username" | "created_at" => (0, user[field].as_str().unwrap_or_default().to_string()),
+        _ => (user[field].as_u64().unwrap_or_default(), String::new()),
+    }
+}
+
+async fn get_users(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> MockResult {
+    let state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    let pairs = query_pairs(query);
+    let usernames = query_values(&pairs, "username");
+    let admins = query_values(&pairs, "admin");
+    let search = query_value(&pairs, "search");
+    let status = query_value(&pairs, "status");
+
+    let mut users: Vec<Value> = state
+        .users
+        .values()
+        .filter(|user| admin.is_sudo || user["admin"]["username"] == admin.username.as_str())
+        .filter(|user| {
+            usernames.is_empty() || usernames.iter().any(|u| user["username"] == u.as_str())
+        })
+        .filter(|user| {
+            admins.is_empty()
+                || admins
+                    .iter()
+                    .any(|a| user["admin"]["username"] == a.as_str())
+        })
+        .filter(|user| status.as_ref().is_none_or(|s| user["status"] == s.as_str()))
+        .filter(|user| {
+            search.as_ref().is_none_or(|search| {
+                user["username"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .contains(search.as_str())
+                    || user["note"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .contains(search.as_str())
+            })
+        })
+        .cloned()
+        .collect();
+
+    if let Some(sort) = query_value(&pairs, "sort") {
+        for option in sort.split(',').rev() {
+            let (field, descending) = match option.trim().strip_prefix('-') {
+                Some(field) => (field, true),
+                None => (option.trim(), false),
+            };
+            users.sort_by(|a, b| {
+                let ordering = sort_key(a, field).cmp(&sort_key(b, field));
+                if descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+        }
+    }
+
+    let total = users.len();
+    ok(json!({ "users": paginate(users, &pairs), "total": total }))
+}
+
+async fn reset_all_users_data_usage(State(state): State<Shared>, headers: HeaderMap) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    for user in state.users.values_mut() {
+        user["used_traffic"] = json!(0);
+    }
+    ok(json!({}))
+}
+
+async fn get_all_users_usage(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> MockResult {
+    let state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    let admins = query_values(&query_pairs(query), "admin");
+    let used_traffic: u64 = state
+        .users
+        .values()
+        .filter(|user| admin.is_sudo || user["admin"]["username"] == admin.username.as_str())
+        .filter(|user| {
+            admins.is_empty()
+                || admins
+                    .iter()
+                    .any(|a| user["admin"]["username"] == a.as_str())
+        })
+        .filter_map(|user| user["used_traffic"].as_u64())
+        .sum();
+    ok(json!({
+        "usages": [{ "node_id": null, "node_name": "Master", "used_traffic": used_traffic }],
+    }))
+}
+
+fn expired_usernames(
+    state: &MockState,
+    admin: &MockAdmin,
+    query: Option<String>,
+) -> Result<Vec<String>, Response> {
+    let pairs = query_pairs(query);
+    let parse = |key: &str| -> Result<Option<i64>, Response> {
+        query_value(&pairs, key)
+            .map(|value| {
+                DateTime::parse_from_rfc3339(&value)
+                    .map(|date| date.timestamp())
+                    .map_err(|_| validation_error(json!(["query", key]), "invalid datetime format"))
+            })
+            .transpose()
+    };
+    let before = parse("expired_before")?.unwrap_or(Utc::now().timestamp());
+    let after = parse("expired_after")?.unwrap_or(0);
+    Ok(state
+        .users
+        .values()
+        .filter(|user| admin.is_sudo || user["admin"]["username"] == admin.username.as_str())
+        .filter(|user| {
+            user["expire"]
+                .as_i64()
+                .is_some_and(|expire| expire > 0 && expire >= after && expire <= before)
+        })
+        .filter_map(|user| user["username"].as_str().map(str::to_owned))
+        .collect())
+}
+
+async fn get_expired_users(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> MockResult {
+    let state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    ok(json!(expired_usernames(&state, &admin, query)?))
+}
+
+async fn delete_expired_users(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> MockResult {
+    let mut state = lock(&state);
+    let admin = state.authorize(&headers)?;
+    let usernames = expired_usernames(&state, &admin, query)?;
+    if usernames.is_empty() {
+        return Err(error(StatusCode::NOT_FOUND, "No expired users found"));
+    }
+    for username in &usernames {
+        state.users.remove(username);
+    }
+    ok(json!(usernames))
+}
+
+fn template_id(id: &str) -> Result<u64, Response> {
+    id.parse().map_err(|_| {
+        validation_error(
+            json!(["path", "template_id"]),
+            "value is not a valid integer",
+        )
+    })
+}
+
+fn template_from_body(id: u64, body: &Value) -> Result<Value, Response> {
+    if body["name"]
+        .as_str()
+        .is_some_and(|name| name.chars().count() > 64)
+    {
+        return Err(validation_error(
+            json!(["body", "name"]),
+            "ensure this value has at most 64 characters",
+        ));
+    }
+    Ok(json!({
+        "name": body["name"],
+        "data_limit": body["data_limit"].as_u64().unwrap_or(0),
+        "expire_duration": body["expire_duration"].as_u64().unwrap_or(0),
+        "username_prefix": body["username_prefix"],
+        "username_suffix": body["username_suffix"],
+        "inbounds": body.get("inbounds").cloned().unwrap_or(json!({})),
+        "id": id,
+    }))
+}
+
+async fn get_user_templates(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> MockResult {
+    let state = lock(&state);
+    state.authorize(&headers)?;
+    let templates = state.templates.values().cloned().collect();
+    ok(Value::Array(paginate(templates, &query_pairs(query))))
+}
+
+async fn add_user_template(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let template = template_from_body(state.next_template_id, &body)?;
+    if !template["name"].is_null()
+        && state
+            .templates
+            .values()
+            .any(|t| t["name"] == template["name"])
+    {
+        return Err(error(
+            StatusCode::CONFLICT,
+            "Template by this name already exists",
+        ));
+    }
+    let id = state.next_template_id;
+    state.next_template_id += 1;
+    state.templates.insert(id, template.clone());
+    ok(template)
+}
+
+async fn get_user_template(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> MockResult {
+    let state = lock(&state);
+    state.authorize(&headers)?;
+    let template = state
+        .templates
+        .get(&template_id(&id)?)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "User Template not found"))?;
+    ok(template.clone())
+}
+
+async fn modify_user_template(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(body): Json<Value>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    let id = template_id(&id)?;
+    if !state.templates.contains_key(&id) {
+        return Err(error(StatusCode::NOT_FOUND, "User Template not found"));
+    }
+    let template = template_from_body(id, &body)?;
+    if !template["name"].is_null()
+        && state
+            .templates
+            .iter()
+            .any(|(other, t)| *other != id && t["name"] == template["name"])
+    {
+        return Err(error(
+            StatusCode::CONFLICT,
+            "Template by this name already exists",
+        ));
+    }
+    state.templates.insert(id, template.clone());
+    ok(template)
+}
+
+async fn remove_user_template(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> MockResult {
+    let mut state = lock(&state);
+    state.authorize_sudo(&headers)?;
+    state
+        .templates
+        .remove(&template_id(&id)?)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "User Template not found"))?;
+    ok(json!({}))
+}
+
+/// The headers Marzban sends alongside every subscription.
+fn subscription_headers(user: &Value) -> Vec<(header::HeaderName, HeaderValue)> {
+    let username = user["username"].as_str().unwrap_or_default();
+    let userinfo = format!(
+        "upload=0; download={}; total={}; expire={}",
+        user["used_traffic"].as_u64().unwrap_or(0),
+        user["data_limit"].as_u64().unwrap_or(0),
+        user["expire"].as_u64().unwrap_or(0),
+    );
+    let header = |name: &'static str, value: String| {
+        (
+            header::HeaderName::from_static(name),
+            HeaderValue::from_str(&value).expect("Invalid mock header"),
+        )
+    };
+    vec![
+        header("subscription-userinfo", userinfo),
+        header("profile-update-interval", "12".to_string()),
+        header(
+            "profile-title",
+            format!("base64:{}", STANDARD.encode(username)),
+        ),
+        header("profile-web-page-url", format!("http://{MOCK_HOST}/sub/")),
+        header("support-url", "https://t.me/support".to_string()),
+        header(
+            "content-disposition",
+            format!("attachment; filename=\"{username}\""),
+        ),
+    ]
+}
+
+/// Render the subscription of a user for a client type, like Marzban's `/sub/{token}/{client_type}`.
+fn render_subscription(user: &Value, client_type: &str) -> Option<(&'static str, String)> {
+    let links: Vec<&str> = user["links"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let proxies = user["proxies"].as_object().cloned().unwrap_or_default();
+    let credential = |protocol: &str, key: &str| {
+        proxies
+            .get(protocol)
+            .and_then(|settings| settings[key].as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let outbounds: Vec<(String, &str, u16)> = INBOUNDS
+        .iter()
+        .filter(|(tag, protocol, ..)| {
+            user["inbounds"][*protocol]
+                .as_array()
+                .is_some_and(|tags| tags.iter().any(|t| t == *tag))
+        })
+        .map(|(tag, protocol, _, _, port)| (tag.to_string(), *protocol, *port))
+        .collect();
+
+    match client_type {
+        "v2ray" => Some(("text/plain", STANDARD.encode(links.join("\n")))),
+        "clash" | "clash-meta" => {
+            let mut yaml = String::from("proxies:\n");
+            for (tag, protocol, port) in &outbounds {
+                let (kind, secret) = match *protocol {
+                    "vmess" => (
+                        "vmess",
+                        format!(
+                            "  uuid: {}\n  alterId: 0\n  cipher: auto\n",
+                            credential("vmess", "id")
+                        ),
+                    ),
+                    "vless" => ("vless", format!("  uuid: {}\n", credential("vless", "id"))),
+                    "trojan" => (
+                        "trojan",
+                        format!("  password: {}\n", credential("trojan", "password")),
+                    ),
+                    _ => (
+                        "ss",
+                        format!(
+                            "  cipher: {}\n  password: {}\n",
+                            credential("shadowsocks", "method"),
+                            credential("shadowsocks", "password")
+                        ),
+                    ),
+                };
+                yaml.push_str(&format!(
+                    "- name: {tag}\n  type: {kind}\n  server: {MOCK_HOST}\n  port: {port}\n{secret}"
+                ));
+            }
+            yaml.push_str("proxy-groups:\n- name: Proxy\n  type: select\n  proxies:\n");
+            for (tag, ..) in &outbounds {
+                yaml.push_str(&format!("  - {tag}\n"));
+            }
+            yaml.push_str("rules:\n- MATCH,Proxy\n");
+            Some(("text/yaml", yaml))
+        }
+        "sing-box" => {
+            let mut entries: Vec<Value> = outbounds
+                .iter()
+                .map(|(tag, protocol, port)| {
+                    let mut outbound = json!({ "type": protocol, "tag": tag, "server": MOCK_HOST, "server_port": port });
+                    match *protocol {
+                        "vmess" | "vless" => outbound["uuid"] = json!(credential(protocol, "id")),
+                        "trojan" => outbound["password"] = json!(credential("trojan", "password")),
+                        _ => {
+                            outbound["method"] = json!(credential("shadowsocks", "method"));
+                            outbound["password"] = json!(credential("shadowsocks", "password"));
+                        }
+                    }
+                    outbound
+                })
+                .collect();
+            entries.push(json!({ "type": "direct", "tag": "direct" }));
+            Some((
+                "application/json",
+                json!({ "log": { "level": "warn" }, "outbounds": entries }).to_string(),
+            ))
+        }
+        "v2ray-json" => {
+            let configs: Vec<Value> = outbounds
+                .iter()
+                .map(|(tag, protocol, port)| {
+                    let settings = match *protocol {
+                        "vmess" | "vless" => json!({ "vnext": [{ "address": MOCK_HOST, "port": port, "users": [{ "id": credential(protocol, "id") }] }] }),
+                        "trojan" => json!({ "servers": [{ "address": MOCK_HOST, "port": port, "password": credential("trojan", "password") }] }),
+                        _ => json!({ "servers": [{ "address": MOCK_HOST, "port": port, "method": credential("shadowsocks", "method"), "password": credential("shadowsocks", "password") }] }),
+                    };
+                    json!({
+                        "remarks": tag,
+                        "outbounds": [
+                            { "tag": "proxy", "protocol": protocol, "settings": settings },
+                            { "tag": "direct", "protocol": "freedom" },
+                        ],
+                    })
+                })
+                .collect();
+            Some(("application/json", Value::Array(configs).to_string()))
+        }
+        "outline" => {
+            let (_, _, port) = outbounds.iter().find(|(_, p, _)| *p == "shadowsocks")?;
+            Some((
+                "application/json",
+                json!({
+                    "server": MOCK_HOST,
+                    "server_port": port,
+                    "password": credential("shadowsocks", "password"),
+                    "method": credential("shadowsocks", "method"),
+                })
+                .to_string(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn subscription_response(user: &Value, content_type: &'static str, body: String) -> Response {
+    let mut response = body.into_response();
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response.headers_mut().extend(subscription_headers(user));
+    response
+}
+
+/// The client type Marzban picks for `/sub/{token}` based on the user agent.
+fn client_type_for_user_agent(user_agent: &str) -> &'static str {
+    let user_agent = user_agent.to_ascii_lowercase();
+    if [
+        "clash-verge",
+        "clash.meta",
+        "clashmeta",
+        "mihomo",
+        "flclash",
+        "clash-meta",
+    ]
+    .iter()
+    .any(|agent| user_agent.contains(agent))
+    {
+        "clash-meta"
+    } else if user_agent.contains("clash") {
+        "clash"
+    } else if ["sing-box", "sfa", "sfi", "sfm", "sft", "hiddify"]
+        .iter()
+        .any(|agent| user_agent.contains(agent))
+    {
+        "sing-box"
+    } else {
+        "v2ray"
+    }
+}
+
+async fn user_subscription(
+    State(state): State<Shared>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> MockResult {
+    let state = lock(&state);
+    let user = state.user_by_token(&token)?;
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let accepts_html = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if accepts_html {
+        return Ok((
+            [(header::CONTENT_TYPE, "text/html")],
+            format!(
+                "<html><body>{}</body></html>",
+                user["username"].as_str().unwrap_or_default()
+            ),
+        )
+            .into_response());
+    }
+    let (content_type, body) = render_subscription(user, client_type_for_user_agent(user_agent))
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Not Found"))?;
+    Ok(subscription_response(user, content_type, body))
+}
+
+async fn user_subscription_info(
+    State(state): State<Shared>,
+    Path(token): Path<String>,
+) -> MockResult {
+    ok(lock(&state).user_by_token(&token)?.clone())
+}
+
+async fn user_subscription_usage(
+    State(state): State<Shared>,
+    Path(token): Path<String>,
+) -> MockResult {
+    let state = lock(&state);
+    let user = state.user_by_token(&token)?;
+    ok(json!({
+        "username": user["username"],
+        "usages": [{ "node_id": null, "node_name": "Master", "used_traffic": user["used_traffic"] }],
+    }))
+}
+
+async fn user_subscription_with_client_type(
+    State(state): State<Shared>,
+    Path((token, client_type)): Path<(String, String)>,
+) -> MockResult {
+    let state = lock(&state);
+    let user = state.user_by_token(&token)?;
+    let (content_type, body) = render_subscription(user, &client_type)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Not Found"))?;
+    Ok(subscription_response(user, content_type, body))
+}
+
+fn default_core_config() -> Value {
+    json!({
+        "log": { "loglevel": "warning" },
+        "api": { "tag": "API", "services": ["HandlerService", "StatsService", "LoggerService"] },
+        "inbounds": [
+            {
+                "tag": "API_INBOUND",
+                "listen": "127.0.0.1",
+                "port": 62051,
+                "protocol": "dokodemo-door",
+                "settings": { "address": "127.0.0.1" }
+            },
+            {
+                "tag": "VMess TCP",
+                "listen": "0.0.0.0",
+                "port": 8080,
+                "protocol": "vmess",
+                "settings": { "clients": [] },
+                "streamSettings": { "network": "tcp", "tcpSettings": { "header": { "type": "none" } }, "security": "none" },
+                "sniffing": { "enabled": true, "destOverride": ["http", "tls", "quic"] }
+            },
+            {
+                "tag": "VLESS TCP REALITY",
+                "listen": "0.0.0.0",
+                "port": 443,
+                "protocol": "vless",
+                "settings": { "clients": [], "decryption": "none" },
+                "streamSettings": {
+                    "network": "tcp",
+                    "security": "reality",
+                    "realitySettings": {
+                        "show": false,
+                        "dest": "www.example.com:443",
+                        "xver": 0,
+                        "serverNames": ["www.example.com"],
+                        "privateKey": "mockprivatekey",
+                        "shortIds": ["6ba85179e30d4fc2"]
+                    }
+                },
+                "sniffing": { "enabled": true, "destOverride": ["http", "tls", "quic"] }
+            },
+            {
+                "tag": "Trojan Websocket TLS",
+                "listen": "0.0.0.0",
+                "port": 2083,
+                "protocol": "trojan",
+                "settings": { "clients": [] },
+                "streamSettings": {
+                    "network": "ws",
+                    "wsSettings": { "path": "/trojan" },
+                    "security": "tls",
+                    "tlsSettings": { "serverName": MOCK_HOST, "alpn": ["h2", "http/1.1"] }
+                }
+            },
+            {
+                "tag": "Shadowsocks TCP",
+                "listen": "0.0.0.0",
+                "port": 1080,
+                "protocol": "shadowsocks",
+                "settings": { "clients": [], "network": "tcp,udp" }
+            }
+        ],
+        "outbounds": [
+            { "protocol": "freedom", "tag": "DIRECT" },
+            { "protocol": "blackhole", "tag": "BLOCK" }
+        ],
+        "routing": {
+            "domainStrategy": "IPIfNonMatch",
+            "rules": [
+                { "type": "field", "inboundTag": ["API_INBOUND"], "outboundTag": "API" },
+                { "type": "field", "ip": ["geoip:private"], "outboundTag": "BLOCK" },
+                { "type": "field", "protocol": ["bittorrent"], "outboundTag": "BLOCK" }
+            ]
+        },
+        "policy": {
+            "levels": { "0": { "statsUserUplink": true, "statsUserDownlink": true } },
+            "system": { "statsInboundDownlink": false, "statsInboundUplink": false }
+        },
+        "stats": {}
+    })
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use crate::models::base::default_empty_string;
 
 // Orignally named: 'Body_admin_token_api_admin_token_post' in the openapi.json
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct BodyAdminTokenApiAdminTokenPost {
     pub grant_type: Option<String>,
     pub username: String,
@@ -1,5 +1,6 @@
 use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Deserializer};
+use validator::ValidationError;
 
 use super::{proxy::ProxyHostSecurity, user::UserDataLimitResetStrategy};
 
@@ -39,6 +40,16 @@ pub(crate) fn default_empty_string() -> String {
     "".to_string()
 }
 
+/// Deserialize `null` as the default value, e.g. an unlimited data limit.
+pub(crate) fn parse_nullable<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    let opt: Option<T> = Deserialize::deserialize(deserializer)?;
+    Ok(opt.unwrap_or_default())
+}
+
 pub(crate) fn parse_datetime<'de, D>(
     deserializer: D,
 ) -> Result<chrono::DateTime<chrono::Utc>, D::Error>
@@ -73,3 +84,17 @@ where
         Ok(None)
     }
 }
+
+/// Marzban's username rule: 3 to 32 characters of a-z, 0-9 and underscores.
+pub(crate) fn validate_username(username: &str) -> Result<(), ValidationError> {
+    let valid_chars = username
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !(3..=32).contains(&username.len()) || !valid_chars {
+        return Err(ValidationError::new("username").with_message(
+            "Username only can be 3 to 32 characters and contain a-z, 0-9, and underscores in between."
+                .into(),
+        ));
+    }
+    Ok(())
+}
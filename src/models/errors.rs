@@ -1,16 +1,106 @@
+use std::{collections::HashMap, fmt};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct HTTPValidationError {
-    detail: Option<Vec<ValidationError>>,
+    pub detail: Option<Vec<ValidationError>>,
+}
+
+impl HTTPValidationError {
+    /// The error messages grouped by the offending field, e.g. `proxies.vmess.id`.
+    ///
+    /// See [`ValidationError::field()`] for how the field names are built.
+    pub fn field_errors(&self) -> HashMap<String, Vec<String>> {
+        let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+        for error in self.detail.iter().flatten() {
+            fields
+                .entry(error.field())
+                .or_default()
+                .push(error.msg.clone());
+        }
+        fields
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ValidationError {
-    pub loc: Vec<ValidationErrorLocation>, // Location
-    pub msg: String,                       // Message
-    pub r#type: String,                    // Error Type
+    pub loc: Vec<LocSegment>, // Location
+    pub msg: String,          // Message
+    pub r#type: String,       // Error Type
+}
+
+impl ValidationError {
+    /// The dotted path of the offending field, without the leading `body`, `query`, `path`,
+    /// `header` or `cookie` segment, e.g. `["body", "inbounds", "vmess", 0]` becomes `inbounds.vmess.0`.
+    pub fn field(&self) -> String {
+        let segments = match self.loc.first() {
+            Some(LocSegment::Key(key))
+                if matches!(
+                    key.as_str(),
+                    "body" | "query" | "path" | "header" | "cookie"
+                ) =>
+            {
+                &self.loc[1..]
+            }
+            _ => &self.loc[..],
+        };
+        segments
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// A segment of a [`ValidationError`] location, either a field name or a list index.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(untagged)]
+pub enum LocSegment {
+    Key(String),
+    Index(u64),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ValidationErrorLocation {}
+impl fmt::Display for LocSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocSegment::Key(key) => write!(f, "{key}"),
+            LocSegment::Index(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fastapi_validation_errors() {
+        let error: HTTPValidationError = serde_json::from_str(
+            r#"{"detail": [
+                {"loc": ["body", "username"], "msg": "too short", "type": "value_error"},
+                {"loc": ["body", "username"], "msg": "invalid characters", "type": "value_error"},
+                {"loc": ["body", "inbounds", "vmess", 0], "msg": "unknown inbound", "type": "value_error"},
+                {"loc": ["query", "limit"], "msg": "not an integer", "type": "type_error.integer"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let detail = error.detail.as_ref().unwrap();
+        assert_eq!(
+            detail[2].loc,
+            vec![
+                LocSegment::Key("body".to_string()),
+                LocSegment::Key("inbounds".to_string()),
+                LocSegment::Key("vmess".to_string()),
+                LocSegment::Index(0),
+            ]
+        );
+
+        let fields = error.field_errors();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields["username"], vec!["too short", "invalid characters"]);
+        assert_eq!(fields["inbounds.vmess.0"], vec!["unknown inbound"]);
+        assert_eq!(fields["limit"], vec!["not an integer"]);
+    }
+}
@@ -12,7 +12,10 @@ pub mod base;
 pub mod errors;
 pub mod node;
 pub mod proxy;
+pub mod subscription;
 pub mod system;
 pub mod token;
+pub mod units;
 pub mod user;
 pub mod user_template;
+pub mod xray;
@@ -21,12 +21,13 @@ pub struct NodeCreate {
     pub add_as_new_host: bool, // default: true
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct NodeModify {
     pub name: Option<String>,
     pub address: Option<String>,
     pub port: Option<u16>,
     pub api_port: Option<u16>,
+    #[validate(range(exclusive_min = 0.0))]
     pub usage_coefficient: Option<f64>,
     pub status: Option<NodeStatus>,
 }
@@ -81,7 +81,7 @@ pub struct ProxyInbound {
     pub port: ProxyInboundPort,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 #[serde(untagged)]
 pub enum ProxyInboundPort {
     String(String),
@@ -0,0 +1,511 @@
+//! Typed subscription responses, as returned by `GET /sub/{token}` and `GET /sub/{token}/{client_type}`.
+//!
+//! Like the [`xray`](crate::models::xray) model, every struct keeps the fields it does not model in `extra`.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    api::subscription::ClientTypes,
+    error::{LinkParseError, SubscriptionDecodeError},
+    links::{decode_base64_lenient, ShareLink},
+    models::xray::XrayConfig,
+};
+
+/// A subscription body with the headers Marzban sends alongside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionResponse {
+    pub body: String,
+    /// The `subscription-userinfo` header, the traffic and expiry shown by clients.
+    pub userinfo: Option<SubscriptionUserInfo>,
+    /// The `profile-update-interval` header, how often clients should refresh the subscription.
+    pub update_interval: Option<Duration>,
+    /// The `profile-title` header, decoded from its `base64:` form.
+    pub title: Option<String>,
+    /// The `profile-web-page-url` header.
+    pub web_page_url: Option<String>,
+    /// The `support-url` header.
+    pub support_url: Option<String>,
+    /// The `content-disposition` header, e.g. `attachment; filename="alice"`.
+    pub content_disposition: Option<String>,
+    pub content_type: Option<String>,
+}
+
+impl SubscriptionResponse {
+    /// Read the known headers of a subscription response.
+    pub(crate) fn from_parts(headers: &HeaderMap, body: String) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        SubscriptionResponse {
+            body,
+            userinfo: header("subscription-userinfo")
+                .map(|value| SubscriptionUserInfo::parse(&value)),
+            update_interval: header("profile-update-interval")
+                .and_then(|hours| hours.trim().parse::<u64>().ok())
+                .and_then(|hours| hours.checked_mul(3600))
+                .map(Duration::from_secs),
+            title: header("profile-title").map(|title| decode_title(&title)),
+            web_page_url: header("profile-web-page-url"),
+            support_url: header("support-url"),
+            content_disposition: header(CONTENT_DISPOSITION.as_str()),
+            content_type: header(CONTENT_TYPE.as_str()),
+        }
+    }
+
+    /// Decode the body, see [`Subscription::decode()`].
+    pub fn decode(
+        &self,
+        client_type: ClientTypes,
+    ) -> Result<Subscription, SubscriptionDecodeError> {
+        Subscription::decode(client_type, &self.body)
+    }
+}
+
+/// Titles are sent as `base64:<title>`, so they may contain any characters.
+fn decode_title(title: &str) -> String {
+    title
+        .strip_prefix("base64:")
+        .and_then(|encoded| decode_base64_lenient(encoded).ok())
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .unwrap_or_else(|| title.to_string())
+}
+
+/// The `subscription-userinfo` header, e.g. `upload=0; download=1024; total=1073741824; expire=1735689600`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubscriptionUserInfo {
+    /// Uploaded bytes. Marzban does not track the direction, so this is always `0`.
+    pub upload: u64,
+    /// Downloaded bytes, the used traffic of the user.
+    pub download: u64,
+    /// The data limit in bytes, or [`None`] if unlimited.
+    pub total: Option<u64>,
+    /// When the subscription expires, or [`None`] if it never does.
+    pub expire: Option<DateTime<Utc>>,
+}
+
+impl SubscriptionUserInfo {
+    /// Parse the header, ignoring unknown or malformed entries. `0` means unlimited for `total` and `expire`.
+    pub fn parse(header: &str) -> Self {
+        let mut info = SubscriptionUserInfo::default();
+        for (key, value) in header
+            .split(';')
+            .filter_map(|entry| entry.split_once('='))
+            .filter_map(|(key, value)| Some((key.trim(), value.trim().parse::<u64>().ok()?)))
+        {
+            match key {
+                "upload" => info.upload = value,
+                "download" => info.download = value,
+                "total" => info.total = Some(value).filter(|total| *total > 0),
+                "expire" => {
+                    info.expire = i64::try_from(value)
+                        .ok()
+                        .filter(|expire| *expire > 0)
+                        .and_then(|expire| DateTime::from_timestamp(expire, 0))
+                }
+                _ => {}
+            }
+        }
+        info
+    }
+
+    /// Used traffic in bytes.
+    pub fn used(&self) -> u64 {
+        self.upload.saturating_add(self.download)
+    }
+
+    /// Remaining traffic in bytes, or [`None`] if unlimited.
+    pub fn remaining(&self) -> Option<u64> {
+        self.total.map(|total| total.saturating_sub(self.used()))
+    }
+}
+
+/// The token of a subscription, as embedded in [`UserResponse::subscription_url`](crate::models::user::UserResponse::subscription_url).
+///
+/// Pass it to the `/sub` methods such as [`MarzbanAPIClient::user_subscription()`](crate::client::MarzbanAPIClient::user_subscription).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SubscriptionToken(String);
+
+/// The data Marzban embeds in a subscription token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionTokenPayload {
+    pub username: String,
+    /// When the token was issued, i.e. the user was created or the subscription last revoked.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Legacy tokens are JWTs with this header.
+const JWT_HEADER: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.";
+
+impl SubscriptionToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        SubscriptionToken(token.into())
+    }
+
+    /// Extract the token from a relative or absolute subscription URL, e.g. `/sub/<token>`
+    /// or `https://sub.example.com/sub/<token>/clash`.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let (_, rest) = url.rsplit_once("/sub/")?;
+        let token = rest.split(['/', '?', '#']).next()?;
+        (!token.is_empty()).then(|| SubscriptionToken::new(token))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Decode the username and creation time, without verifying the signature.
+    ///
+    /// Returns [`None`] if the token is not in one of Marzban's formats: the base64 encoded
+    /// `username,created_at` followed by a 10 character signature, or a legacy JWT.
+    pub fn payload(&self) -> Option<SubscriptionTokenPayload> {
+        if self.0.len() < 15 {
+            return None;
+        }
+        if let Some(jwt) = self.0.strip_prefix(JWT_HEADER) {
+            #[derive(Deserialize)]
+            struct Claims {
+                sub: String,
+                access: String,
+                iat: i64,
+            }
+            let (claims, _signature) = jwt.split_once('.')?;
+            let claims: Claims =
+                serde_json::from_slice(&decode_base64_lenient(claims).ok()?).ok()?;
+            return (claims.access == "subscription").then_some(SubscriptionTokenPayload {
+                username: claims.sub,
+                created_at: DateTime::from_timestamp(claims.iat, 0)?,
+            });
+        }
+        // `get` as the token may not be ASCII, in which case the index may split a character.
+        let data = decode_base64_lenient(self.0.get(..self.0.len() - 10)?).ok()?;
+        let (username, created_at) = std::str::from_utf8(&data).ok()?.rsplit_once(',')?;
+        Some(SubscriptionTokenPayload {
+            username: username.to_string(),
+            created_at: DateTime::from_timestamp(created_at.parse().ok()?, 0)?,
+        })
+    }
+
+    /// The subscription URL under `prefix`, e.g. `https://sub.example.com`, optionally for a client type.
+    pub fn url(&self, prefix: &str, client_type: Option<ClientTypes>) -> String {
+        let url = format!("{}/sub/{}", prefix.trim_end_matches('/'), self.0);
+        match client_type {
+            Some(client_type) => format!("{url}/{client_type}"),
+            None => url,
+        }
+    }
+}
+
+impl AsRef<str> for SubscriptionToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SubscriptionToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A decoded subscription, see [`Subscription::decode()`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subscription {
+    /// The share links, one per inbound.
+    V2Ray(Vec<String>),
+    /// One Xray client configuration per inbound.
+    V2RayJson(Vec<XrayConfig>),
+    Clash(ClashConfig),
+    ClashMeta(ClashConfig),
+    SingBox(SingBoxConfig),
+    Outline(OutlineConfig),
+}
+
+impl Subscription {
+    /// Decode a subscription body of the given client type.
+    pub fn decode(client_type: ClientTypes, body: &str) -> Result<Self, SubscriptionDecodeError> {
+        Ok(match client_type {
+            ClientTypes::V2Ray => Subscription::V2Ray(decode_links(body)?),
+            ClientTypes::V2RayJSON => Subscription::V2RayJson(serde_json::from_str(body)?),
+            ClientTypes::Clash => Subscription::Clash(serde_yaml::from_str(body)?),
+            ClientTypes::ClashMeta => Subscription::ClashMeta(serde_yaml::from_str(body)?),
+            ClientTypes::SingBox => Subscription::SingBox(serde_json::from_str(body)?),
+            ClientTypes::Outline => Subscription::Outline(serde_json::from_str(body)?),
+        })
+    }
+
+    /// The client type this subscription was decoded as.
+    pub fn client_type(&self) -> ClientTypes {
+        match self {
+            Subscription::V2Ray(_) => ClientTypes::V2Ray,
+            Subscription::V2RayJson(_) => ClientTypes::V2RayJSON,
+            Subscription::Clash(_) => ClientTypes::Clash,
+            Subscription::ClashMeta(_) => ClientTypes::ClashMeta,
+            Subscription::SingBox(_) => ClientTypes::SingBox,
+            Subscription::Outline(_) => ClientTypes::Outline,
+        }
+    }
+
+    /// Parse the share links of a [`Subscription::V2Ray`], or an empty iterator for other types.
+    pub fn share_links(&self) -> impl Iterator<Item = Result<ShareLink, LinkParseError>> + '_ {
+        let links = match self {
+            Subscription::V2Ray(links) => links.as_slice(),
+            _ => &[],
+        };
+        links.iter().map(|link| link.parse())
+    }
+}
+
+/// Decode the newline separated links, which Marzban encodes with base64 unless they are plain already.
+fn decode_links(body: &str) -> Result<Vec<String>, SubscriptionDecodeError> {
+    let text = if body.contains("://") {
+        body.to_string()
+    } else {
+        let bytes = decode_base64_lenient(body)?;
+        std::str::from_utf8(&bytes)?.to_string()
+    };
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct ClashConfig {
+    #[serde(default)]
+    pub proxies: Vec<ClashProxy>,
+    #[serde(rename = "proxy-groups", default)]
+    pub proxy_groups: Vec<ClashProxyGroup>,
+    #[serde(default)]
+    pub rules: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ClashProxy {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub proxy_type: String, // vmess, vless, trojan, ss
+    pub server: String,
+    pub port: u16,
+    /// The credentials and transport, e.g. `uuid`, `password`, `network` or `ws-opts`.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ClashProxyGroup {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub group_type: String, // select, url-test, fallback, load-balance
+    #[serde(default)]
+    pub proxies: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct SingBoxConfig {
+    #[serde(default)]
+    pub outbounds: Vec<SingBoxOutbound>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SingBoxOutbound {
+    #[serde(rename = "type")]
+    pub outbound_type: String, // vmess, vless, trojan, shadowsocks, direct, selector, urltest, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_port: Option<u16>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The Shadowsocks access key of Outline.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct OutlineConfig {
+    pub server: String,
+    pub server_port: u16,
+    pub password: String,
+    pub method: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{
+        engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+        Engine,
+    };
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn extracts_tokens() {
+        let token = format!("{}AbCdEfGhIj", URL_SAFE_NO_PAD.encode("alice,1735689600"));
+        for url in [
+            format!("/sub/{token}"),
+            format!("https://sub.example.com/sub/{token}/"),
+            format!("https://example.com/panel/sub/{token}/clash-meta?x=1"),
+        ] {
+            assert_eq!(SubscriptionToken::from_url(&url).unwrap().as_str(), token);
+        }
+        assert_eq!(
+            SubscriptionToken::from_url("https://example.com/dashboard"),
+            None
+        );
+
+        let token = SubscriptionToken::new(token);
+        let payload = token.payload().unwrap();
+        assert_eq!(payload.username, "alice");
+        assert_eq!(payload.created_at.timestamp(), 1735689600);
+        assert_eq!(
+            token.url("https://sub.example.com/", Some(ClientTypes::SingBox)),
+            format!("https://sub.example.com/sub/{token}/sing-box")
+        );
+        assert_eq!(SubscriptionToken::new("short").payload(), None);
+        assert_eq!(SubscriptionToken::new("€€€€€").payload(), None);
+    }
+
+    #[test]
+    fn decodes_legacy_jwt_tokens() {
+        let claims =
+            URL_SAFE_NO_PAD.encode(r#"{"sub":"bob","access":"subscription","iat":1700000000}"#);
+        let token = SubscriptionToken::new(format!("{JWT_HEADER}{claims}.signature"));
+        let payload = token.payload().unwrap();
+        assert_eq!(payload.username, "bob");
+        assert_eq!(payload.created_at.timestamp(), 1700000000);
+    }
+
+    #[test]
+    fn parses_headers() {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            (
+                "subscription-userinfo",
+                "upload=0; download=1024; total=4096; expire=1735689600",
+            ),
+            ("profile-update-interval", "12"),
+            ("profile-title", "base64:YWxpY2U="),
+            ("content-disposition", "attachment; filename=\"alice\""),
+        ] {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+        let response = SubscriptionResponse::from_parts(&headers, String::new());
+        let userinfo = response.userinfo.unwrap();
+        assert_eq!(userinfo.remaining(), Some(3072));
+        assert_eq!(userinfo.expire.unwrap().timestamp(), 1735689600);
+        assert_eq!(
+            response.update_interval,
+            Some(Duration::from_secs(12 * 3600))
+        );
+        assert_eq!(response.title.as_deref(), Some("alice"));
+        assert_eq!(response.support_url, None);
+
+        let unlimited = SubscriptionUserInfo::parse("upload=0; download=10; total=0; expire=0");
+        assert_eq!(
+            (unlimited.total, unlimited.expire, unlimited.remaining()),
+            (None, None, None)
+        );
+
+        let huge =
+            SubscriptionUserInfo::parse(&format!("upload={0}; download={0}; total={0}", u64::MAX));
+        assert_eq!((huge.used(), huge.remaining()), (u64::MAX, Some(0)));
+        headers.insert(
+            "profile-update-interval",
+            HeaderValue::from_str(&u64::MAX.to_string()).unwrap(),
+        );
+        let response = SubscriptionResponse::from_parts(&headers, String::new());
+        assert_eq!(response.update_interval, None);
+    }
+
+    #[test]
+    fn decodes_v2ray_links() {
+        let links = "vless://id@example.com:443?security=tls&type=tcp#VLESS\nss://YWVzLTI1Ni1nY206c2VjcmV0@example.com:1080#SS\n";
+        for body in [STANDARD.encode(links), links.to_string()] {
+            let subscription = Subscription::decode(ClientTypes::V2Ray, &body).unwrap();
+            assert!(matches!(&subscription, Subscription::V2Ray(links) if links.len() == 2));
+            let remarks: Vec<_> = subscription
+                .share_links()
+                .map(|link| link.unwrap().remark().to_string())
+                .collect();
+            assert_eq!(remarks, ["VLESS", "SS"]);
+        }
+    }
+
+    #[test]
+    fn decodes_clash() {
+        let body = "\
+mode: rule
+proxies:
+- name: VMess TCP
+  type: vmess
+  server: example.com
+  port: 8080
+  uuid: a6f7bb0c-2b4d-4b9a-9c2f-34a3f5f0d1b7
+  alterId: 0
+  cipher: auto
+proxy-groups:
+- name: Proxy
+  type: select
+  proxies:
+  - VMess TCP
+rules:
+- MATCH,Proxy
+";
+        let Subscription::ClashMeta(clash) =
+            Subscription::decode(ClientTypes::ClashMeta, body).unwrap()
+        else {
+            unreachable!()
+        };
+        assert_eq!(clash.proxies[0].proxy_type, "vmess");
+        assert_eq!(clash.proxies[0].port, 8080);
+        assert_eq!(clash.proxies[0].extra["cipher"], "auto");
+        assert_eq!(clash.proxy_groups[0].proxies, ["VMess TCP"]);
+        assert_eq!(clash.rules, ["MATCH,Proxy"]);
+        assert_eq!(clash.extra["mode"], "rule");
+    }
+
+    #[test]
+    fn decodes_json_types() {
+        let sing_box = r#"{"log":{"level":"warn"},"outbounds":[{"type":"trojan","tag":"Trojan","server":"example.com","server_port":443,"password":"secret"},{"type":"direct","tag":"direct"}]}"#;
+        let Subscription::SingBox(config) =
+            Subscription::decode(ClientTypes::SingBox, sing_box).unwrap()
+        else {
+            unreachable!()
+        };
+        assert_eq!(config.outbounds[0].server_port, Some(443));
+        assert_eq!(config.outbounds[1].server, None);
+
+        let v2ray_json = r#"[{"remarks":"VLESS","outbounds":[{"tag":"proxy","protocol":"vless","settings":{}}]}]"#;
+        let Subscription::V2RayJson(configs) =
+            Subscription::decode(ClientTypes::V2RayJSON, v2ray_json).unwrap()
+        else {
+            unreachable!()
+        };
+        assert_eq!(configs[0].outbound("proxy").unwrap().protocol, "vless");
+        assert_eq!(configs[0].extra["remarks"], "VLESS");
+
+        assert!(matches!(
+            Subscription::decode(ClientTypes::Outline, "{}"),
+            Err(SubscriptionDecodeError::Json(_))
+        ));
+    }
+}
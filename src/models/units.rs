@@ -0,0 +1,316 @@
+//! Human-friendly units for the user models: [`ByteSize`] for data limits and usage, and [`Expiry`] for
+//! account expiration. Both serialize to the plain numbers Marzban expects.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::ByteSizeParseError;
+
+/// The binary units used for display, largest first.
+const BINARY_UNITS: [(&str, u64); 5] = [
+    ("PiB", 1 << 50),
+    ("TiB", 1 << 40),
+    ("GiB", 1 << 30),
+    ("MiB", 1 << 20),
+    ("KiB", 1 << 10),
+];
+
+/// A number of bytes, e.g. a data limit or the used traffic. `0` means unlimited for data limits.
+///
+/// Displays with binary units, e.g. `10 GiB` or `1.5 MiB`, and parses both binary (`KiB`, `MiB`, ...)
+/// and decimal (`KB`, `MB`, ...) units, case-insensitively. A number without unit is in bytes.
+///
+/// ## Example
+///
+/// ```
+/// use marzban_api::models::units::ByteSize;
+///
+/// let limit: ByteSize = "10 GiB".parse().unwrap();
+/// assert_eq!(limit, ByteSize::gib(10));
+/// assert_eq!(limit.bytes(), 10 * 1024 * 1024 * 1024);
+/// assert_eq!(ByteSize::mib(1536).to_string(), "1.5 GiB");
+/// ```
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug,
+)]
+#[serde(transparent)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    pub const ZERO: ByteSize = ByteSize(0);
+
+    pub const fn b(bytes: u64) -> Self {
+        ByteSize(bytes)
+    }
+
+    pub const fn kib(kib: u64) -> Self {
+        ByteSize(kib.saturating_mul(1 << 10))
+    }
+
+    pub const fn mib(mib: u64) -> Self {
+        ByteSize(mib.saturating_mul(1 << 20))
+    }
+
+    pub const fn gib(gib: u64) -> Self {
+        ByteSize(gib.saturating_mul(1 << 30))
+    }
+
+    pub const fn tib(tib: u64) -> Self {
+        ByteSize(tib.saturating_mul(1 << 40))
+    }
+
+    pub const fn bytes(self) -> u64 {
+        self.0
+    }
+
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn saturating_sub(self, other: ByteSize) -> Self {
+        ByteSize(self.0.saturating_sub(other.0))
+    }
+}
+
+impl From<u64> for ByteSize {
+    fn from(bytes: u64) -> Self {
+        ByteSize(bytes)
+    }
+}
+
+impl From<ByteSize> for u64 {
+    fn from(size: ByteSize) -> Self {
+        size.0
+    }
+}
+
+impl std::fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some((unit, factor)) = BINARY_UNITS.iter().find(|(_, factor)| self.0 >= *factor) else {
+            return write!(f, "{} B", self.0);
+        };
+        // Two decimals at most, without trailing zeros.
+        let value = format!("{:.2}", self.0 as f64 / *factor as f64);
+        let value = value.trim_end_matches('0').trim_end_matches('.');
+        write!(f, "{value} {unit}")
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = ByteSizeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (number, unit) = (&s[..split], s[split..].trim());
+        let factor: u64 = match unit.to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1_000,
+            "mb" => 1_000_000,
+            "gb" => 1_000_000_000,
+            "tb" => 1_000_000_000_000,
+            "pb" => 1_000_000_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            "tib" => 1 << 40,
+            "pib" => 1 << 50,
+            _ => return Err(ByteSizeParseError::UnknownUnit(unit.to_string())),
+        };
+        let invalid = || ByteSizeParseError::Invalid(s.to_string());
+        if let Ok(integer) = number.parse::<u64>() {
+            return integer
+                .checked_mul(factor)
+                .map(ByteSize)
+                .ok_or_else(invalid);
+        }
+        let bytes = number.parse::<f64>().map_err(|_| invalid())? * factor as f64;
+        if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+            return Err(invalid());
+        }
+        Ok(ByteSize(bytes.round() as u64))
+    }
+}
+
+/// When an account expires, serialized as the UTC timestamp with `0` for [`Expiry::Never`].
+///
+/// Deserializes both `0` and `null` to [`Expiry::Never`].
+///
+/// ## Example
+///
+/// ```
+/// use chrono::TimeDelta;
+/// use marzban_api::models::units::Expiry;
+///
+/// let expiry = Expiry::after(TimeDelta::days(30));
+/// assert!(!expiry.is_expired());
+/// assert_eq!(Expiry::from_timestamp(0), Expiry::Never);
+/// ```
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub enum Expiry {
+    #[default]
+    Never,
+    At(DateTime<Utc>),
+}
+
+impl Expiry {
+    /// The expiry `delta` from now.
+    pub fn after(delta: TimeDelta) -> Self {
+        Expiry::At(Utc::now() + delta)
+    }
+
+    /// The expiry of a UTC timestamp, `0` means never.
+    ///
+    /// Timestamps beyond the range of [`DateTime`] are clamped to [`DateTime::<Utc>::MAX_UTC`].
+    pub fn from_timestamp(timestamp: u64) -> Self {
+        if timestamp == 0 {
+            return Expiry::Never;
+        }
+        let at = i64::try_from(timestamp)
+            .ok()
+            .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        Expiry::At(at)
+    }
+
+    /// The UTC timestamp, `0` for [`Expiry::Never`].
+    ///
+    /// Dates up to the epoch are clamped to `1`, as `0` would mean never, so they stay expired.
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            Expiry::Never => 0,
+            Expiry::At(at) => at.timestamp().max(1).unsigned_abs(),
+        }
+    }
+
+    pub fn datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Expiry::Never => None,
+            Expiry::At(at) => Some(*at),
+        }
+    }
+
+    pub fn is_never(&self) -> bool {
+        *self == Expiry::Never
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.datetime().is_some_and(|at| at <= now)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
+}
+
+impl From<DateTime<Utc>> for Expiry {
+    fn from(at: DateTime<Utc>) -> Self {
+        Expiry::At(at)
+    }
+}
+
+impl From<u64> for Expiry {
+    fn from(timestamp: u64) -> Self {
+        Expiry::from_timestamp(timestamp)
+    }
+}
+
+impl std::fmt::Display for Expiry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expiry::Never => write!(f, "never"),
+            Expiry::At(at) => write!(f, "{at}"),
+        }
+    }
+}
+
+impl Serialize for Expiry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.timestamp())
+    }
+}
+
+impl<'de> Deserialize<'de> for Expiry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let timestamp: Option<u64> = Deserialize::deserialize(deserializer)?;
+        Ok(timestamp.map_or(Expiry::Never, Expiry::from_timestamp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_displays_byte_sizes() {
+        for (input, bytes) in [
+            ("1024", 1024),
+            ("10 GiB", 10 << 30),
+            ("10gib", 10 << 30),
+            ("1.5 MiB", 1536 << 10),
+            ("2 GB", 2_000_000_000),
+            (" 512 b ", 512),
+        ] {
+            assert_eq!(
+                input.parse::<ByteSize>().unwrap(),
+                ByteSize(bytes),
+                "{input}"
+            );
+        }
+        for input in ["", "GiB", "1.2.3 GiB", "20000000 PiB"] {
+            assert!(matches!(
+                input.parse::<ByteSize>(),
+                Err(ByteSizeParseError::Invalid(_))
+            ));
+        }
+        assert!(matches!(
+            "10 parsecs".parse::<ByteSize>(),
+            Err(ByteSizeParseError::UnknownUnit(unit)) if unit == "parsecs"
+        ));
+
+        assert_eq!(ByteSize::ZERO.to_string(), "0 B");
+        assert_eq!(ByteSize(1023).to_string(), "1023 B");
+        assert_eq!(ByteSize::gib(10).to_string(), "10 GiB");
+        assert_eq!(ByteSize(1_000_000_000).to_string(), "953.67 MiB");
+    }
+
+    #[test]
+    fn serializes_to_marzban_values() {
+        assert_eq!(serde_json::to_string(&ByteSize::kib(1)).unwrap(), "1024");
+        assert_eq!(serde_json::to_string(&Expiry::Never).unwrap(), "0");
+        let expiry = Expiry::from_timestamp(1_900_000_000);
+        assert_eq!(serde_json::to_string(&expiry).unwrap(), "1900000000");
+        for (json, expected) in [
+            ("null", Expiry::Never),
+            ("0", Expiry::Never),
+            ("1900000000", expiry),
+        ] {
+            assert_eq!(serde_json::from_str::<Expiry>(json).unwrap(), expected);
+        }
+        assert!(expiry.is_expired_at(DateTime::from_timestamp(1_900_000_000, 0).unwrap()));
+        assert!(!Expiry::Never.is_expired());
+    }
+
+    #[test]
+    fn clamps_out_of_range_expiries() {
+        for at in [
+            DateTime::UNIX_EPOCH,
+            DateTime::from_timestamp(-86400, 0).unwrap(),
+        ] {
+            let expiry = Expiry::At(at);
+            assert_eq!(expiry.timestamp(), 1);
+            assert_eq!(serde_json::to_string(&expiry).unwrap(), "1");
+            assert!(expiry.is_expired());
+        }
+
+        for timestamp in [u64::MAX, i64::MAX as u64] {
+            let expiry = Expiry::from_timestamp(timestamp);
+            assert_eq!(expiry, Expiry::At(DateTime::<Utc>::MAX_UTC));
+            assert!(!expiry.is_expired());
+        }
+    }
+}
@@ -1,19 +1,30 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use validator::{Validate, ValidationErrors};
 
 use crate::models::base::{
-    default_data_limit_reset_strategy, default_empty_string, parse_datetime, parse_some_datetime,
+    default_data_limit_reset_strategy, default_empty_string, parse_datetime, parse_nullable,
+    parse_some_datetime, validate_username,
+};
+use crate::{
+    api::subscription::ClientTypes,
+    error::LinkParseError,
+    links::ShareLink,
+    models::{
+        subscription::SubscriptionToken,
+        units::{ByteSize, Expiry},
+    },
 };
 
 use super::admin::Admin;
 
-#[derive(Serialize, Deserialize, Validate, Debug)]
+#[derive(Serialize, Deserialize, Validate, Clone, Debug)]
 pub struct UserCreate {
     pub proxies: Proxies,
-    pub expire: Option<u64>,
-    #[validate(range(min = 0))]
-    pub data_limit: u64, // min: 0, can be 0 or greater
+    #[serde(default)]
+    pub expire: Expiry,
+    #[serde(default, deserialize_with = "parse_nullable")]
+    pub data_limit: ByteSize, // 0 means unlimited
     #[serde(default = "default_data_limit_reset_strategy")]
     pub data_limit_reset_strategy: UserDataLimitResetStrategy, // default: no_reset
     pub inbounds: Inbounds,
@@ -30,11 +41,12 @@ pub struct UserCreate {
     pub on_hold_timeout: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auto_delete_in_days: Option<u64>,
+    #[validate(custom(function = "validate_username"))]
     pub username: String,
     pub status: UserStatusCreate,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum UserDataLimitResetStrategy {
     #[serde(rename = "no_reset")]
     NoReset,
@@ -48,34 +60,41 @@ pub enum UserDataLimitResetStrategy {
     Year,
 }
 
-#[derive(Serialize, Deserialize, Validate, Debug)]
+/// Modifications to an existing user. Fields which are [`None`] are omitted and left unchanged.
+#[derive(Serialize, Deserialize, Validate, Clone, Default, Debug)]
 pub struct UserModify {
-    pub proxies: Proxies,
-    pub expire: Option<u64>,
-    #[validate(range(min = 0))]
-    pub data_limit: u64, // min: 0, can be 0 or greater
-    pub data_limit_reset_strategy: UserDataLimitResetStrategy,
-    pub inbounds: Inbounds,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxies: Option<Proxies>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire: Option<Expiry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_limit: Option<ByteSize>, // 0 means unlimited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_limit_reset_strategy: Option<UserDataLimitResetStrategy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inbounds: Option<Inbounds>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub note: Option<String>,
-    #[serde(deserialize_with = "parse_some_datetime")]
+    #[serde(default, deserialize_with = "parse_some_datetime")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sub_updated_at: Option<DateTime<Utc>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sub_last_user_agent: Option<String>,
-    #[serde(deserialize_with = "parse_some_datetime")]
+    #[serde(default, deserialize_with = "parse_some_datetime")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub online_at: Option<DateTime<Utc>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub on_hold_expire_duration: Option<u64>,
-    #[serde(deserialize_with = "parse_some_datetime")]
+    #[serde(default, deserialize_with = "parse_some_datetime")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub on_hold_timeout: Option<DateTime<Utc>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub auto_delete_in_days: Option<u64>,
-    pub status: UserStatusModify,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<UserStatusModify>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
 pub struct Proxies {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub trojan: Option<Trojan>,
@@ -87,7 +106,7 @@ pub struct Proxies {
     pub shadowsocks: Option<Shadowsocks>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
 pub struct Trojan {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
@@ -95,7 +114,7 @@ pub struct Trojan {
     pub flow: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
 pub struct Vless {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
@@ -103,7 +122,7 @@ pub struct Vless {
     pub flow: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
 pub struct Vmess {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
@@ -111,7 +130,7 @@ pub struct Vmess {
     pub security: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
 pub struct Shadowsocks {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
@@ -119,7 +138,7 @@ pub struct Shadowsocks {
     pub method: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
 pub struct Inbounds {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub trojan: Option<Vec<String>>,
@@ -134,9 +153,10 @@ pub struct Inbounds {
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct UserResponse {
     pub proxies: Proxies,
-    pub expire: Option<u64>,
-    #[validate(range(min = 0))]
-    pub data_limit: Option<u64>, // min: 0, can be 0 or greater
+    #[serde(default)]
+    pub expire: Expiry,
+    #[serde(default, deserialize_with = "parse_nullable")]
+    pub data_limit: ByteSize, // 0 means unlimited
     pub data_limit_reset_strategy: UserDataLimitResetStrategy, // default: no_reset
     pub inbounds: Inbounds,
     pub note: Option<String>,
@@ -151,8 +171,9 @@ pub struct UserResponse {
     pub auto_delete_in_days: Option<u64>,
     pub username: String,
     pub status: UserStatus,
-    pub used_traffic: u64,
-    pub lifetime_used_traffic: u64, // default: 0
+    pub used_traffic: ByteSize,
+    #[serde(default)]
+    pub lifetime_used_traffic: ByteSize,
     #[serde(deserialize_with = "parse_datetime")]
     pub created_at: DateTime<Utc>,
     pub links: Vec<String>,
@@ -162,7 +183,36 @@ pub struct UserResponse {
     pub admin: Admin,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl UserResponse {
+    /// Parse the share [`links`](UserResponse::links), see [`crate::links`].
+    pub fn share_links(&self) -> impl Iterator<Item = Result<ShareLink, LinkParseError>> + '_ {
+        self.links.iter().map(|link| link.parse())
+    }
+
+    /// The token of the [`subscription_url`](UserResponse::subscription_url).
+    pub fn subscription_token(&self) -> Option<SubscriptionToken> {
+        SubscriptionToken::from_url(&self.subscription_url)
+    }
+
+    /// The absolute subscription URL, optionally for a client type.
+    ///
+    /// The `subscription_url` is relative unless `XRAY_SUBSCRIPTION_URL_PREFIX` is set,
+    /// in which case it is resolved against `base_url`, the URL of the panel.
+    pub fn subscription_url_for(
+        &self,
+        base_url: &str,
+        client_type: Option<ClientTypes>,
+    ) -> Option<String> {
+        let token = self.subscription_token()?;
+        let prefix = match self.subscription_url.rsplit_once("/sub/") {
+            Some((prefix, _)) if prefix.contains("://") => prefix,
+            _ => base_url,
+        };
+        Some(token.url(prefix, client_type))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum UserStatus {
     #[serde(rename = "active")]
     Active,
@@ -176,7 +226,19 @@ pub enum UserStatus {
     OnHold,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl std::fmt::Display for UserStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserStatus::Active => write!(f, "active"),
+            UserStatus::Disabled => write!(f, "disabled"),
+            UserStatus::Limited => write!(f, "limited"),
+            UserStatus::Expired => write!(f, "expired"),
+            UserStatus::OnHold => write!(f, "on_hold"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum UserStatusCreate {
     #[serde(rename = "active")]
     Active,
@@ -184,7 +246,7 @@ pub enum UserStatusCreate {
     OnHold,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum UserStatusModify {
     #[serde(rename = "active")]
     Active,
@@ -198,7 +260,7 @@ pub enum UserStatusModify {
 pub struct UserUsageResponse {
     pub node_id: Option<u64>,
     pub node_name: String,
-    pub used_traffic: u64,
+    pub used_traffic: ByteSize,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -215,5 +277,286 @@ pub struct UsersResponse {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UsersUsagesResponse {
-    pub users: Vec<UserUsagesResponse>,
+    pub usages: Vec<UserUsageResponse>,
+}
+
+impl UserCreate {
+    /// Create a [`UserCreateBuilder`] for a new user with the given username.
+    ///
+    /// The user is `active`, has no expiry and no data limit unless configured otherwise.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use marzban_api::models::{
+    ///     units::ByteSize,
+    ///     user::{UserCreate, Vless},
+    /// };
+    ///
+    /// let user = UserCreate::builder("alice")
+    ///     .vless(Vless::default())
+    ///     .data_limit(ByteSize::gib(10))
+    ///     .note("Paid until June")
+    ///     .build()
+    ///     .expect("Invalid user");
+    /// ```
+    pub fn builder(username: impl Into<String>) -> UserCreateBuilder {
+        UserCreateBuilder {
+            user: UserCreate {
+                proxies: Proxies::default(),
+                expire: Expiry::Never,
+                data_limit: ByteSize::ZERO,
+                data_limit_reset_strategy: UserDataLimitResetStrategy::NoReset,
+                inbounds: Inbounds::default(),
+                note: None,
+                sub_updated_at: None,
+                sub_last_user_agent: None,
+                online_at: None,
+                on_hold_expire_duration: None,
+                on_hold_timeout: None,
+                auto_delete_in_days: None,
+                username: username.into(),
+                status: UserStatusCreate::Active,
+            },
+        }
+    }
+}
+
+/// A builder for [`UserCreate`], see [`UserCreate::builder()`].
+#[derive(Debug, Clone)]
+pub struct UserCreateBuilder {
+    user: UserCreate,
+}
+
+impl UserCreateBuilder {
+    /// Replace all proxy settings.
+    pub fn proxies(mut self, proxies: Proxies) -> Self {
+        self.user.proxies = proxies;
+        self
+    }
+
+    /// Enable the VMess proxy. Leave the `id` empty to let the server generate one.
+    pub fn vmess(mut self, vmess: Vmess) -> Self {
+        self.user.proxies.vmess = Some(vmess);
+        self
+    }
+
+    /// Enable the VLESS proxy. Leave the `id` empty to let the server generate one.
+    pub fn vless(mut self, vless: Vless) -> Self {
+        self.user.proxies.vless = Some(vless);
+        self
+    }
+
+    /// Enable the Trojan proxy. Leave the `password` empty to let the server generate one.
+    pub fn trojan(mut self, trojan: Trojan) -> Self {
+        self.user.proxies.trojan = Some(trojan);
+        self
+    }
+
+    /// Enable the Shadowsocks proxy. Leave the `password` empty to let the server generate one.
+    pub fn shadowsocks(mut self, shadowsocks: Shadowsocks) -> Self {
+        self.user.proxies.shadowsocks = Some(shadowsocks);
+        self
+    }
+
+    /// Restrict the inbounds per protocol. Protocols without inbounds use all of them.
+    pub fn inbounds(mut self, inbounds: Inbounds) -> Self {
+        self.user.inbounds = inbounds;
+        self
+    }
+
+    /// Account expiration, a [`DateTime<Utc>`] or UTC timestamp. `0` means unlimited.
+    pub fn expire(mut self, expire: impl Into<Expiry>) -> Self {
+        self.user.expire = expire.into();
+        self
+    }
+
+    /// Max data usage, a [`ByteSize`] or bytes. `0` means unlimited.
+    pub fn data_limit(mut self, data_limit: impl Into<ByteSize>) -> Self {
+        self.user.data_limit = data_limit.into();
+        self
+    }
+
+    pub fn data_limit_reset_strategy(mut self, strategy: UserDataLimitResetStrategy) -> Self {
+        self.user.data_limit_reset_strategy = strategy;
+        self
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.user.note = Some(note.into());
+        self
+    }
+
+    pub fn status(mut self, status: UserStatusCreate) -> Self {
+        self.user.status = status;
+        self
+    }
+
+    /// Duration (in seconds) for how long the user should stay in `on_hold` status.
+    pub fn on_hold_expire_duration(mut self, seconds: u64) -> Self {
+        self.user.on_hold_expire_duration = Some(seconds);
+        self
+    }
+
+    /// UTC timestamp when `on_hold` status should start or end.
+    pub fn on_hold_timeout(mut self, timeout: DateTime<Utc>) -> Self {
+        self.user.on_hold_timeout = Some(timeout);
+        self
+    }
+
+    pub fn auto_delete_in_days(mut self, days: u64) -> Self {
+        self.user.auto_delete_in_days = Some(days);
+        self
+    }
+
+    /// Validate and build the [`UserCreate`].
+    pub fn build(self) -> Result<UserCreate, ValidationErrors> {
+        self.user.validate()?;
+        Ok(self.user)
+    }
+}
+
+impl UserModify {
+    /// Create a [`UserModifyBuilder`], which only changes the fields that are set.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use marzban_api::models::user::UserModify;
+    ///
+    /// let modification = UserModify::builder()
+    ///     .note("Moved to the yearly plan")
+    ///     .build()
+    ///     .expect("Invalid modification");
+    /// ```
+    pub fn builder() -> UserModifyBuilder {
+        UserModifyBuilder {
+            modify: UserModify::default(),
+        }
+    }
+}
+
+/// A builder for [`UserModify`], see [`UserModify::builder()`].
+#[derive(Debug, Clone, Default)]
+pub struct UserModifyBuilder {
+    modify: UserModify,
+}
+
+impl UserModifyBuilder {
+    /// Replace all proxy settings.
+    pub fn proxies(mut self, proxies: Proxies) -> Self {
+        self.modify.proxies = Some(proxies);
+        self
+    }
+
+    /// Replace the inbounds per protocol.
+    pub fn inbounds(mut self, inbounds: Inbounds) -> Self {
+        self.modify.inbounds = Some(inbounds);
+        self
+    }
+
+    /// Account expiration, a [`DateTime<Utc>`] or UTC timestamp. `0` means unlimited.
+    pub fn expire(mut self, expire: impl Into<Expiry>) -> Self {
+        self.modify.expire = Some(expire.into());
+        self
+    }
+
+    /// Max data usage, a [`ByteSize`] or bytes. `0` means unlimited.
+    pub fn data_limit(mut self, data_limit: impl Into<ByteSize>) -> Self {
+        self.modify.data_limit = Some(data_limit.into());
+        self
+    }
+
+    pub fn data_limit_reset_strategy(mut self, strategy: UserDataLimitResetStrategy) -> Self {
+        self.modify.data_limit_reset_strategy = Some(strategy);
+        self
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.modify.note = Some(note.into());
+        self
+    }
+
+    pub fn status(mut self, status: UserStatusModify) -> Self {
+        self.modify.status = Some(status);
+        self
+    }
+
+    /// Duration (in seconds) for how long the user should stay in `on_hold` status.
+    pub fn on_hold_expire_duration(mut self, seconds: u64) -> Self {
+        self.modify.on_hold_expire_duration = Some(seconds);
+        self
+    }
+
+    /// UTC timestamp when `on_hold` status should start or end.
+    pub fn on_hold_timeout(mut self, timeout: DateTime<Utc>) -> Self {
+        self.modify.on_hold_timeout = Some(timeout);
+        self
+    }
+
+    pub fn auto_delete_in_days(mut self, days: u64) -> Self {
+        self.modify.auto_delete_in_days = Some(days);
+        self
+    }
+
+    /// Validate and build the [`UserModify`].
+    pub fn build(self) -> Result<UserModify, ValidationErrors> {
+        self.modify.validate()?;
+        Ok(self.modify)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_modify_only_serializes_set_fields() {
+        let modify = UserModify::builder().note("hello").build().unwrap();
+        assert_eq!(
+            serde_json::to_value(&modify).unwrap(),
+            serde_json::json!({ "note": "hello" })
+        );
+        assert_eq!(
+            serde_json::to_value(UserModify::default()).unwrap(),
+            serde_json::json!({})
+        );
+    }
+
+    #[test]
+    fn username_rule() {
+        for username in ["abc", "user_01", "a_very_long_username_of_32_chars"] {
+            assert!(UserCreate::builder(username).build().is_ok(), "{username}");
+        }
+        for username in [
+            "ab",
+            "Alice",
+            "user-01",
+            "user name",
+            "a_very_long_username_of_33_chars_",
+        ] {
+            assert!(UserCreate::builder(username).build().is_err(), "{username}");
+        }
+    }
+
+    #[test]
+    fn create_builder_fills_defaults() {
+        let user = UserCreate::builder("alice")
+            .vless(Vless::default())
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&user).unwrap(),
+            serde_json::json!({
+                "proxies": { "vless": {} },
+                "expire": 0,
+                "data_limit": 0,
+                "data_limit_reset_strategy": "no_reset",
+                "inbounds": {},
+                "note": null,
+                "username": "alice",
+                "status": "active",
+            })
+        );
+    }
 }
@@ -3,14 +3,13 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use crate::models::base::default_with_0;
+use crate::models::{base::default_with_0, units::ByteSize};
 
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct UserTemplateCreate {
     pub name: Option<String>,
-    #[serde(default = "default_with_0")]
-    #[validate(range(min = 0))]
-    pub data_limit: u64, // default: 0, can be 0 or greater
+    #[serde(default)]
+    pub data_limit: ByteSize, // default: 0
     #[serde(default = "default_with_0")]
     pub expire_duration: u64, // default: 0, can be 0 or greater
     #[validate(length(min = 1, max = 20))]
@@ -23,9 +22,8 @@ pub struct UserTemplateCreate {
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct UserTemplateModify {
     pub name: Option<String>,
-    #[serde(default = "default_with_0")]
-    #[validate(range(min = 0))]
-    pub data_limit: u64, // default: 0, can be 0 or greater
+    #[serde(default)]
+    pub data_limit: ByteSize, // default: 0
     #[serde(default = "default_with_0")]
     pub expire_duration: u64, // default: 0, can be 0 or greater
     #[validate(length(min = 1, max = 20))]
@@ -38,9 +36,8 @@ pub struct UserTemplateModify {
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct UserTemplateResponse {
     pub name: Option<String>,
-    #[serde(default = "default_with_0")]
-    #[validate(range(min = 0))]
-    pub data_limit: u64, // default: 0, can be 0 or greater
+    #[serde(default)]
+    pub data_limit: ByteSize, // default: 0
     #[serde(default = "default_with_0")]
     pub expire_duration: u64, // default: 0, can be 0 or greater
     #[validate(length(min = 1, max = 20))]
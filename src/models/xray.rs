@@ -0,0 +1,752 @@
+//! Typed model of the Xray core configuration, as returned by `GET /api/core/config`.
+//!
+//! Every struct keeps the fields it does not model, and explicit `null`s, in `extra`, so a
+//! configuration round-trips without losing anything.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::models::proxy::ProxyInboundPort;
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct XrayConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<LogConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api: Option<ApiConfig>,
+    #[serde(default)]
+    pub inbounds: Vec<Inbound>,
+    #[serde(default)]
+    pub outbounds: Vec<Outbound>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routing: Option<RoutingConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Map<String, Value>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct LogConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loglevel: Option<String>, // debug, info, warning, error, none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_log: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct ApiConfig {
+    pub tag: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub services: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct Inbound {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<ProxyInboundPort>,
+    /// The `protocol` and its `settings`.
+    #[serde(flatten)]
+    pub protocol: InboundProtocol,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_settings: Option<StreamSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sniffing: Option<Sniffing>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The protocol of an inbound, with its protocol specific settings.
+///
+/// Protocols which are not modelled, or whose settings could not be parsed, are kept as [`InboundProtocol::Other`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(from = "RawProtocol", into = "RawProtocol")]
+pub enum InboundProtocol {
+    Vmess(VmessInboundSettings),
+    Vless(VlessInboundSettings),
+    Trojan(TrojanInboundSettings),
+    Shadowsocks(ShadowsocksInboundSettings),
+    Other {
+        protocol: String,
+        settings: Option<Value>,
+    },
+}
+
+/// The wire format of [`InboundProtocol`]: sibling `protocol` and `settings` fields.
+#[derive(Serialize, Deserialize)]
+struct RawProtocol {
+    protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    settings: Option<Value>,
+}
+
+impl From<RawProtocol> for InboundProtocol {
+    fn from(raw: RawProtocol) -> Self {
+        fn parse<T: serde::de::DeserializeOwned>(settings: &Value) -> Option<T> {
+            serde_json::from_value(settings.clone()).ok()
+        }
+
+        let parsed = raw
+            .settings
+            .as_ref()
+            .and_then(|settings| match raw.protocol.as_str() {
+                "vmess" => parse(settings).map(InboundProtocol::Vmess),
+                "vless" => parse(settings).map(InboundProtocol::Vless),
+                "trojan" => parse(settings).map(InboundProtocol::Trojan),
+                "shadowsocks" => parse(settings).map(InboundProtocol::Shadowsocks),
+                _ => None,
+            });
+        parsed.unwrap_or(InboundProtocol::Other {
+            protocol: raw.protocol,
+            settings: raw.settings,
+        })
+    }
+}
+
+impl From<InboundProtocol> for RawProtocol {
+    fn from(protocol: InboundProtocol) -> Self {
+        fn to_value<T: Serialize>(settings: T) -> Option<Value> {
+            // Serializing these structs into a `Value` cannot fail, as all their keys are strings.
+            serde_json::to_value(settings).ok()
+        }
+
+        let (protocol, settings) = match protocol {
+            InboundProtocol::Vmess(settings) => ("vmess".to_string(), to_value(settings)),
+            InboundProtocol::Vless(settings) => ("vless".to_string(), to_value(settings)),
+            InboundProtocol::Trojan(settings) => ("trojan".to_string(), to_value(settings)),
+            InboundProtocol::Shadowsocks(settings) => {
+                ("shadowsocks".to_string(), to_value(settings))
+            }
+            InboundProtocol::Other { protocol, settings } => (protocol, settings),
+        };
+        RawProtocol { protocol, settings }
+    }
+}
+
+impl InboundProtocol {
+    /// The protocol name, e.g. `vless`.
+    pub fn name(&self) -> &str {
+        match self {
+            InboundProtocol::Vmess(_) => "vmess",
+            InboundProtocol::Vless(_) => "vless",
+            InboundProtocol::Trojan(_) => "trojan",
+            InboundProtocol::Shadowsocks(_) => "shadowsocks",
+            InboundProtocol::Other { protocol, .. } => protocol,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct VmessInboundSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clients: Option<Vec<VmessClient>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct VmessClient {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u32>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct VlessInboundSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clients: Option<Vec<VlessClient>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decryption: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallbacks: Option<Vec<Value>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct VlessClient {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u32>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct TrojanInboundSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clients: Option<Vec<TrojanClient>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallbacks: Option<Vec<Value>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct TrojanClient {
+    pub password: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u32>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct ShadowsocksInboundSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clients: Option<Vec<ShadowsocksClient>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>, // tcp, udp or tcp,udp
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct ShadowsocksClient {
+    pub password: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct StreamSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>, // tcp, ws, grpc, httpupgrade, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security: Option<String>, // none, tls, reality
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_settings: Option<TlsSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reality_settings: Option<RealitySettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ws_settings: Option<WsSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc_settings: Option<GrpcSettings>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct TlsSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificates: Option<Vec<Value>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct RealitySettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dest: Option<Value>, // "host:port" or a port number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xver: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_names: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_ids: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct WsSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct GrpcSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multi_mode: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct Sniffing {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dest_override: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_only: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct Outbound {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    pub protocol: String, // freedom, blackhole, vless, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_settings: Option<StreamSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_through: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct RoutingConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain_strategy: Option<String>, // AsIs, IPIfNonMatch, IPOnDemand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Vec<RoutingRule>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balancers: Option<Vec<Value>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct RoutingRule {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub rule_type: Option<String>, // field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<ProxyInboundPort>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inbound_tag: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outbound_tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balancer_tag: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct PolicyConfig {
+    /// Policies per user level, keyed by the level as a string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub levels: Option<BTreeMap<String, LevelPolicy>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemPolicy>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct LevelPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handshake: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conn_idle: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uplink_only: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downlink_only: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_user_uplink: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_user_downlink: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer_size: Option<u32>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase", remote = "Self")]
+pub struct SystemPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_inbound_uplink: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_inbound_downlink: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_outbound_uplink: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_outbound_downlink: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Implements `Serialize` and `Deserialize` around the impls derived with `#[serde(remote = "Self")]`,
+/// moving explicit `null`s into `extra`. The derived impls read a `null` as `None`, and would skip it
+/// when serializing.
+macro_rules! keep_nulls {
+    ($($model:ident),* $(,)?) => {$(
+        impl Serialize for $model {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if !self.extra.values().any(Value::is_null) {
+                    return $model::serialize(self, serializer);
+                }
+                // A field set since parsing takes precedence over its `null`.
+                let (nulls, extra) = self.extra.clone().into_iter().partition(|(_, v)| v.is_null());
+                let model = $model { extra, ..self.clone() };
+                let mut value = $model::serialize(&model, serde_json::value::Serializer)
+                    .map_err(ser::Error::custom)?;
+                if let Value::Object(map) = &mut value {
+                    for (key, null) in nulls {
+                        map.entry(key).or_insert(null);
+                    }
+                }
+                value.serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $model {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let map = Map::deserialize(deserializer)?;
+                let (nulls, map): (Map<_, _>, _) = map.into_iter().partition(|(_, v)| v.is_null());
+                let mut model =
+                    $model::deserialize(Value::Object(map)).map_err(de::Error::custom)?;
+                model.extra.extend(nulls);
+                Ok(model)
+            }
+        }
+    )*};
+}
+
+keep_nulls!(
+    XrayConfig,
+    LogConfig,
+    ApiConfig,
+    Inbound,
+    VmessInboundSettings,
+    VmessClient,
+    VlessInboundSettings,
+    VlessClient,
+    TrojanInboundSettings,
+    TrojanClient,
+    ShadowsocksInboundSettings,
+    ShadowsocksClient,
+    StreamSettings,
+    TlsSettings,
+    RealitySettings,
+    WsSettings,
+    GrpcSettings,
+    Sniffing,
+    Outbound,
+    RoutingConfig,
+    RoutingRule,
+    PolicyConfig,
+    LevelPolicy,
+    SystemPolicy,
+);
+
+impl XrayConfig {
+    /// The inbound with the given tag.
+    pub fn inbound(&self, tag: &str) -> Option<&Inbound> {
+        self.inbounds
+            .iter()
+            .find(|inbound| inbound.tag.as_deref() == Some(tag))
+    }
+
+    /// The inbound with the given tag, mutably.
+    pub fn inbound_mut(&mut self, tag: &str) -> Option<&mut Inbound> {
+        self.inbounds
+            .iter_mut()
+            .find(|inbound| inbound.tag.as_deref() == Some(tag))
+    }
+
+    /// The outbound with the given tag.
+    pub fn outbound(&self, tag: &str) -> Option<&Outbound> {
+        self.outbounds
+            .iter()
+            .find(|outbound| outbound.tag.as_deref() == Some(tag))
+    }
+
+    /// The routing rules, or an empty slice if there are none.
+    pub fn rules(&self) -> &[RoutingRule] {
+        self.routing
+            .as_ref()
+            .and_then(|routing| routing.rules.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+fn duplicate_tags<'a>(tags: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut seen = BTreeSet::new();
+    tags.filter(|tag| !seen.insert(*tag)).collect()
+}
+
+/// Checks the mistakes which would keep the core from starting: duplicate inbound or outbound
+/// tags, and routing rules pointing at outbounds or balancers which do not exist.
+impl Validate for XrayConfig {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let error = |code, message: String| ValidationError::new(code).with_message(message.into());
+
+        for tag in duplicate_tags(self.inbounds.iter().filter_map(|i| i.tag.as_ref())) {
+            errors.add(
+                "inbounds",
+                error("unique", format!("Duplicate inbound tag {tag}")),
+            );
+        }
+        for tag in duplicate_tags(self.outbounds.iter().filter_map(|o| o.tag.as_ref())) {
+            errors.add(
+                "outbounds",
+                error("unique", format!("Duplicate outbound tag {tag}")),
+            );
+        }
+
+        let outbounds: BTreeSet<_> = self
+            .outbounds
+            .iter()
+            .filter_map(|outbound| outbound.tag.as_deref())
+            // Rules route the API inbound to the API, which is not an outbound.
+            .chain(self.api.as_ref().map(|api| api.tag.as_str()))
+            .collect();
+        let balancers: BTreeSet<_> = self
+            .routing
+            .iter()
+            .flat_map(|routing| routing.balancers.iter().flatten())
+            .filter_map(|balancer| balancer.get("tag")?.as_str())
+            .collect();
+        for (index, rule) in self.rules().iter().enumerate() {
+            if let Some(tag) = rule
+                .outbound_tag
+                .as_deref()
+                .filter(|t| !outbounds.contains(t))
+            {
+                errors.add(
+                    "routing",
+                    error(
+                        "outbound_tag",
+                        format!("Rule #{index} routes to unknown outbound {tag}"),
+                    ),
+                );
+            }
+            if let Some(tag) = rule
+                .balancer_tag
+                .as_deref()
+                .filter(|t| !balancers.contains(t))
+            {
+                errors.add(
+                    "routing",
+                    error(
+                        "balancer_tag",
+                        format!("Rule #{index} routes to unknown balancer {tag}"),
+                    ),
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn marzban_config() -> Value {
+        json!({
+            "log": { "loglevel": "warning" },
+            "routing": {
+                "rules": [
+                    { "ip": ["geoip:private"], "outboundTag": "BLOCK", "type": "field" }
+                ]
+            },
+            "inbounds": [
+                {
+                    "tag": "VLESS TCP REALITY",
+                    "listen": "0.0.0.0",
+                    "port": 8443,
+                    "protocol": "vless",
+                    "settings": { "clients": [], "decryption": "none" },
+                    "streamSettings": {
+                        "network": "tcp",
+                        "tcpSettings": {},
+                        "security": "reality",
+                        "realitySettings": {
+                            "show": false,
+                            "dest": "tgju.org:443",
+                            "xver": 0,
+                            "serverNames": ["tgju.org"],
+                            "privateKey": "key",
+                            "shortIds": [""]
+                        }
+                    },
+                    "sniffing": { "enabled": true, "destOverride": ["http", "tls", "quic"] }
+                },
+                {
+                    "tag": "Dokodemo",
+                    "port": "10000-10010",
+                    "protocol": "dokodemo-door",
+                    "settings": { "address": "127.0.0.1" }
+                }
+            ],
+            "outbounds": [
+                { "protocol": "freedom", "tag": "DIRECT" },
+                { "protocol": "blackhole", "tag": "BLOCK" }
+            ],
+            "fakedns": [{ "ipPool": "198.18.0.0/15", "poolSize": 65535 }]
+        })
+    }
+
+    #[test]
+    fn round_trips_without_loss() {
+        let config: XrayConfig = serde_json::from_value(marzban_config()).unwrap();
+        assert_eq!(serde_json::to_value(&config).unwrap(), marzban_config());
+    }
+
+    #[test]
+    fn round_trips_explicit_nulls() {
+        let json = json!({
+            "log": null,
+            "stats": null,
+            "inbounds": [
+                {
+                    "tag": "VLESS TCP",
+                    "port": null,
+                    "protocol": "vless",
+                    "settings": { "clients": [], "decryption": null },
+                    "streamSettings": { "network": "tcp", "tlsSettings": null }
+                },
+                { "tag": "Dokodemo", "protocol": "dokodemo-door", "settings": null }
+            ],
+            "outbounds": [{ "protocol": "freedom", "tag": null, "settings": null }]
+        });
+        let mut config: XrayConfig = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(config.log, None);
+        assert!(matches!(
+            config.inbounds[0].protocol,
+            InboundProtocol::Vless(_)
+        ));
+        assert_eq!(serde_json::to_value(&config).unwrap(), json);
+
+        // A field set after parsing replaces its null.
+        config.log = Some(LogConfig::default());
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(value["log"], json!({}));
+    }
+
+    #[test]
+    fn parses_protocol_settings() {
+        let config: XrayConfig = serde_json::from_value(marzban_config()).unwrap();
+
+        let vless = config.inbound("VLESS TCP REALITY").unwrap();
+        match &vless.protocol {
+            InboundProtocol::Vless(settings) => {
+                assert_eq!(settings.decryption.as_deref(), Some("none"));
+            }
+            other => panic!("Expected vless settings, got {other:?}"),
+        }
+        let reality = vless
+            .stream_settings
+            .as_ref()
+            .unwrap()
+            .reality_settings
+            .as_ref();
+        assert_eq!(
+            reality.unwrap().server_names,
+            Some(vec!["tgju.org".to_string()])
+        );
+
+        let dokodemo = config.inbound("Dokodemo").unwrap();
+        assert_eq!(dokodemo.protocol.name(), "dokodemo-door");
+        assert!(matches!(dokodemo.protocol, InboundProtocol::Other { .. }));
+        assert!(config.extra.contains_key("fakedns"));
+        assert_eq!(config.rules()[0].outbound_tag.as_deref(), Some("BLOCK"));
+    }
+
+    #[test]
+    fn validates_tags() {
+        let mut config: XrayConfig = serde_json::from_value(marzban_config()).unwrap();
+        assert!(config.validate().is_ok());
+
+        config
+            .outbounds
+            .retain(|outbound| outbound.tag.as_deref() != Some("BLOCK"));
+        config.inbounds.push(config.inbounds[0].clone());
+        let errors = config.validate().unwrap_err();
+        let errors = errors.field_errors();
+        assert_eq!(errors["inbounds"][0].code, "unique");
+        assert_eq!(errors["routing"][0].code, "outbound_tag");
+        assert!(!errors.contains_key("outbounds"));
+    }
+}
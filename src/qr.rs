@@ -0,0 +1,121 @@
+//! # QR module
+//!
+//! This module renders share links and subscription URLs to QR codes, as PNG, SVG or Unicode blocks.
+//!
+//! Requires the `qr` feature.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use marzban_api::client::MarzbanAPIClient;
+//!
+//! # async fn example(client: MarzbanAPIClient) -> Result<(), Box<dyn std::error::Error>> {
+//! let user = client.get_user("alice").await?;
+//! let code = user.subscription_qr_code("https://panel.example.com")?;
+//! println!("{}", code.to_unicode());
+//! std::fs::write("alice.png", code.to_png()?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::Cursor;
+
+use image::{ImageFormat, Luma};
+use qrcode::render::{svg, unicode};
+
+use crate::{error::QrError, models::user::UserResponse};
+
+/// The minimum width and height of rendered images, in pixels.
+const MIN_DIMENSIONS: u32 = 256;
+
+/// A QR code of a link or URL.
+#[derive(Clone)]
+pub struct QrCode {
+    code: qrcode::QrCode,
+}
+
+impl std::fmt::Debug for QrCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QrCode")
+            .field("width", &self.code.width())
+            .finish()
+    }
+}
+
+impl QrCode {
+    /// Encode `data`, e.g. a share link or subscription URL.
+    pub fn new(data: impl AsRef<[u8]>) -> Result<Self, QrError> {
+        Ok(QrCode {
+            code: qrcode::QrCode::new(data)?,
+        })
+    }
+
+    /// Render to PNG bytes.
+    pub fn to_png(&self) -> Result<Vec<u8>, QrError> {
+        let image = self
+            .code
+            .render::<Luma<u8>>()
+            .min_dimensions(MIN_DIMENSIONS, MIN_DIMENSIONS)
+            .build();
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok(png)
+    }
+
+    /// Render to an SVG document.
+    pub fn to_svg(&self) -> String {
+        self.code
+            .render::<svg::Color>()
+            .min_dimensions(MIN_DIMENSIONS, MIN_DIMENSIONS)
+            .build()
+    }
+
+    /// Render to Unicode half blocks, two modules per character, for printing to a terminal.
+    ///
+    /// The colors are inverted, so the code scans on the usual light on dark terminal.
+    pub fn to_unicode(&self) -> String {
+        self.code
+            .render::<unicode::Dense1x2>()
+            .dark_color(unicode::Dense1x2::Light)
+            .light_color(unicode::Dense1x2::Dark)
+            .build()
+    }
+}
+
+impl UserResponse {
+    /// QR codes of the share [`links`](UserResponse::links), in the same order.
+    pub fn link_qr_codes(&self) -> Result<Vec<QrCode>, QrError> {
+        self.links.iter().map(QrCode::new).collect()
+    }
+
+    /// QR code of the absolute subscription URL, see [`UserResponse::subscription_url_for()`].
+    pub fn subscription_qr_code(&self, base_url: &str) -> Result<QrCode, QrError> {
+        match self.subscription_url_for(base_url, None) {
+            Some(url) => QrCode::new(url),
+            None => QrCode::new(&self.subscription_url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_formats() {
+        let code = QrCode::new("vless://id@example.com:443?security=tls#VLESS").unwrap();
+        assert!(code.to_png().unwrap().starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert!(code.to_svg().contains("<svg"));
+        let unicode = code.to_unicode();
+        assert!(unicode.lines().count() > 10);
+        assert!(unicode.contains('█'));
+    }
+
+    #[test]
+    fn rejects_too_long_data() {
+        assert!(matches!(
+            QrCode::new("x".repeat(8000)),
+            Err(QrError::Encode(_))
+        ));
+    }
+}
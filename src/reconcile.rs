@@ -0,0 +1,483 @@
+//! # Reconcile module
+//!
+//! This module syncs the users of the panel to a desired state: it diffs the desired users against
+//! the current ones into an inspectable [`Plan`] of creates, modifies and deletes, and applies it
+//! through [`MarzbanAPIClient::add_user()`], [`MarzbanAPIClient::modify_user()`] and
+//! [`MarzbanAPIClient::delete_user()`].
+//!
+//! Only the fields the desired users specify are reconciled: expiry, data limit and its reset strategy,
+//! the enabled protocols if any are set, explicitly listed inbounds, the note if set, and whether the user is disabled.
+//! Generated credentials are kept, and the `limited`, `expired` and `on_hold` statuses Marzban manages are left alone.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use marzban_api::{
+//!     client::MarzbanAPIClient,
+//!     models::user::{UserCreate, Vless},
+//!     reconcile::ReconcileOptions,
+//! };
+//!
+//! # async fn example(client: MarzbanAPIClient) -> Result<(), marzban_api::error::ApiError> {
+//! let desired = vec![UserCreate::builder("alice").vless(Vless::default()).build()?];
+//! let options = ReconcileOptions {
+//!     prune: true,
+//!     dry_run: true,
+//!     ..Default::default()
+//! };
+//! let reconciliation = client.reconcile(desired, options).await?;
+//! println!("{}", reconciliation.plan);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+
+use futures::TryStreamExt;
+
+use crate::{
+    api::user::GetUsersQueryParams,
+    bulk::{BulkOptions, BulkReport},
+    client::MarzbanAPIClient,
+    error::ApiError,
+    models::user::{
+        Inbounds, Proxies, UserCreate, UserModify, UserResponse, UserStatus, UserStatusModify,
+    },
+};
+
+/// The desired state of a single user.
+#[derive(Debug, Clone)]
+pub struct DesiredUser {
+    pub user: UserCreate,
+    /// Keep the user disabled. New users are created and disabled right after, as Marzban
+    /// only creates `active` or `on_hold` users.
+    pub disabled: bool,
+}
+
+impl From<UserCreate> for DesiredUser {
+    fn from(user: UserCreate) -> Self {
+        DesiredUser {
+            user,
+            disabled: false,
+        }
+    }
+}
+
+/// A planned modification of an existing user.
+#[derive(Debug, Clone)]
+pub struct PlannedModify {
+    pub username: String,
+    /// The names of the changed fields, e.g. `expire` or `proxies`.
+    pub changes: Vec<&'static str>,
+    pub modify: UserModify,
+}
+
+/// The changes needed to bring the panel to the desired state, see [`MarzbanAPIClient::plan_reconciliation()`].
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub create: Vec<DesiredUser>,
+    pub modify: Vec<PlannedModify>,
+    /// Users which are not desired, only planned with [`ReconcileOptions::prune`] set.
+    pub delete: Vec<String>,
+    /// Number of users which already match the desired state.
+    pub unchanged: usize,
+}
+
+impl Plan {
+    /// Diff the `desired` users against the `current` ones.
+    pub fn new(
+        desired: impl IntoIterator<Item = impl Into<DesiredUser>>,
+        current: &[UserResponse],
+        prune: bool,
+    ) -> Self {
+        let current_by_name: HashMap<_, _> = current
+            .iter()
+            .map(|user| (user.username.as_str(), user))
+            .collect();
+        let mut plan = Plan::default();
+        let mut desired_names = BTreeSet::new();
+        for desired in desired.into_iter().map(Into::into) {
+            desired_names.insert(desired.user.username.clone());
+            match current_by_name.get(desired.user.username.as_str()) {
+                None => plan.create.push(desired),
+                Some(current) => match modification(&desired, current) {
+                    Some(modify) => plan.modify.push(modify),
+                    None => plan.unchanged += 1,
+                },
+            }
+        }
+        if prune {
+            plan.delete = current
+                .iter()
+                .filter(|user| !desired_names.contains(&user.username))
+                .map(|user| user.username.clone())
+                .collect();
+        }
+        plan
+    }
+
+    /// Whether the panel already matches the desired state.
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.modify.is_empty() && self.delete.is_empty()
+    }
+}
+
+impl std::fmt::Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for desired in &self.create {
+            writeln!(f, "+ {}", desired.user.username)?;
+        }
+        for modify in &self.modify {
+            writeln!(f, "~ {}: {}", modify.username, modify.changes.join(", "))?;
+        }
+        for username in &self.delete {
+            writeln!(f, "- {username}")?;
+        }
+        writeln!(f, "{} unchanged", self.unchanged)
+    }
+}
+
+/// Fill the credentials the desired proxies leave empty with the current ones, so they are kept.
+fn merge_proxies(desired: &Proxies, current: &Proxies) -> Proxies {
+    fn merge<T: Clone>(
+        desired: &Option<T>,
+        current: &Option<T>,
+        fill: fn(&mut T, &T),
+    ) -> Option<T> {
+        let mut merged = desired.clone()?;
+        if let Some(current) = current {
+            fill(&mut merged, current);
+        }
+        Some(merged)
+    }
+    fn or(value: &mut Option<String>, current: &Option<String>) {
+        if value.is_none() {
+            value.clone_from(current);
+        }
+    }
+
+    Proxies {
+        vmess: merge(&desired.vmess, &current.vmess, |d, c| {
+            or(&mut d.id, &c.id);
+            or(&mut d.security, &c.security);
+        }),
+        vless: merge(&desired.vless, &current.vless, |d, c| {
+            or(&mut d.id, &c.id);
+            or(&mut d.flow, &c.flow);
+        }),
+        trojan: merge(&desired.trojan, &current.trojan, |d, c| {
+            or(&mut d.password, &c.password);
+            or(&mut d.flow, &c.flow);
+        }),
+        shadowsocks: merge(&desired.shadowsocks, &current.shadowsocks, |d, c| {
+            or(&mut d.password, &c.password);
+            or(&mut d.method, &c.method);
+        }),
+    }
+}
+
+/// Use the explicitly listed inbounds, keeping the current ones for the other protocols.
+fn merge_inbounds(desired: &Inbounds, current: &Inbounds) -> Inbounds {
+    Inbounds {
+        vmess: desired.vmess.clone().or_else(|| current.vmess.clone()),
+        vless: desired.vless.clone().or_else(|| current.vless.clone()),
+        trojan: desired.trojan.clone().or_else(|| current.trojan.clone()),
+        shadowsocks: desired
+            .shadowsocks
+            .clone()
+            .or_else(|| current.shadowsocks.clone()),
+    }
+}
+
+/// Inbounds compared regardless of their order.
+fn same_inbounds(a: &Inbounds, b: &Inbounds) -> bool {
+    let set = |tags: &Option<Vec<String>>| tags.iter().flatten().cloned().collect::<BTreeSet<_>>();
+    set(&a.vmess) == set(&b.vmess)
+        && set(&a.vless) == set(&b.vless)
+        && set(&a.trojan) == set(&b.trojan)
+        && set(&a.shadowsocks) == set(&b.shadowsocks)
+}
+
+/// The modification bringing `current` to the `desired` state, if any is needed.
+fn modification(desired: &DesiredUser, current: &UserResponse) -> Option<PlannedModify> {
+    let user = &desired.user;
+    let mut modify = UserModify::default();
+    let mut changes = Vec::new();
+
+    if user.expire != current.expire {
+        modify.expire = Some(user.expire);
+        changes.push("expire");
+    }
+    if user.data_limit != current.data_limit {
+        modify.data_limit = Some(user.data_limit);
+        changes.push("data_limit");
+    }
+    if user.data_limit_reset_strategy != current.data_limit_reset_strategy {
+        modify.data_limit_reset_strategy = Some(user.data_limit_reset_strategy);
+        changes.push("data_limit_reset_strategy");
+    }
+    // Without any protocol, the current proxies are kept.
+    let proxies = merge_proxies(&user.proxies, &current.proxies);
+    if user.proxies != Proxies::default() && proxies != current.proxies {
+        modify.proxies = Some(proxies);
+        changes.push("proxies");
+    }
+    let inbounds = merge_inbounds(&user.inbounds, &current.inbounds);
+    if !same_inbounds(&inbounds, &current.inbounds) {
+        modify.inbounds = Some(inbounds);
+        changes.push("inbounds");
+    }
+    if user.note.is_some() && user.note != current.note {
+        modify.note.clone_from(&user.note);
+        changes.push("note");
+    }
+    let disabled = current.status == UserStatus::Disabled;
+    if desired.disabled != disabled {
+        modify.status = Some(if desired.disabled {
+            UserStatusModify::Disabled
+        } else {
+            UserStatusModify::Active
+        });
+        changes.push("status");
+    }
+
+    (!changes.is_empty()).then(|| PlannedModify {
+        username: current.username.clone(),
+        changes,
+        modify,
+    })
+}
+
+/// Options for [`MarzbanAPIClient::reconcile()`].
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileOptions {
+    /// Delete the users which are not desired. Defaults to `false`.
+    pub prune: bool,
+    /// Only plan, without applying anything. Defaults to `false`.
+    pub dry_run: bool,
+    /// The concurrency and stop on errors of applying the plan.
+    pub bulk: BulkOptions,
+}
+
+/// The outcome of applying a [`Plan`], see [`MarzbanAPIClient::apply_plan()`].
+#[derive(Debug)]
+pub struct PlanReport {
+    pub created: BulkReport<UserResponse>,
+    /// The modified users, including new users which were disabled after being created.
+    pub modified: BulkReport<UserResponse>,
+    pub deleted: BulkReport<String>,
+}
+
+impl PlanReport {
+    /// Whether every operation succeeded.
+    pub fn is_success(&self) -> bool {
+        self.created.is_success() && self.modified.is_success() && self.deleted.is_success()
+    }
+}
+
+/// The outcome of [`MarzbanAPIClient::reconcile()`].
+#[derive(Debug)]
+pub struct Reconciliation {
+    pub plan: Plan,
+    /// [`None`] for a dry run.
+    pub report: Option<PlanReport>,
+}
+
+impl MarzbanAPIClient {
+    /// `GET /api/users` (paginated)
+    ///
+    /// Plan the changes bringing the users of the current admin to the `desired` state, see [`Plan`].
+    pub async fn plan_reconciliation(
+        &self,
+        desired: impl IntoIterator<Item = impl Into<DesiredUser>>,
+        prune: bool,
+    ) -> Result<Plan, ApiError> {
+        let current: Vec<UserResponse> = self
+            .users_stream(GetUsersQueryParams::default())
+            .try_collect()
+            .await?;
+        Ok(Plan::new(desired, &current, prune))
+    }
+
+    /// Apply a [`Plan`]: create, then modify, then delete the users.
+    pub async fn apply_plan(&self, plan: &Plan, options: BulkOptions) -> PlanReport {
+        let created = self
+            .bulk_add_users_with_options(
+                plan.create.iter().map(|desired| desired.user.clone()),
+                options.clone(),
+            )
+            .await;
+        let disable_created = plan
+            .create
+            .iter()
+            .filter(|desired| desired.disabled)
+            .filter(|desired| {
+                created
+                    .succeeded()
+                    .any(|(name, _)| name == desired.user.username)
+            })
+            .map(|desired| {
+                let modify = UserModify {
+                    status: Some(UserStatusModify::Disabled),
+                    ..Default::default()
+                };
+                (desired.user.username.clone(), modify)
+            });
+        let modifications = plan
+            .modify
+            .iter()
+            .map(|planned| (planned.username.clone(), planned.modify.clone()))
+            .chain(disable_created)
+            .collect::<Vec<_>>();
+        let modified = self
+            .bulk_modify_users_with_options(modifications, options.clone())
+            .await;
+        let deleted = self
+            .bulk_delete_users_with_options(plan.delete.iter().cloned(), options)
+            .await;
+        PlanReport {
+            created,
+            modified,
+            deleted,
+        }
+    }
+
+    /// Plan the changes bringing the users to the `desired` state and apply them, unless it is a dry run.
+    pub async fn reconcile(
+        &self,
+        desired: impl IntoIterator<Item = impl Into<DesiredUser>>,
+        options: ReconcileOptions,
+    ) -> Result<Reconciliation, ApiError> {
+        let plan = self.plan_reconciliation(desired, options.prune).await?;
+        let report = if options.dry_run {
+            None
+        } else {
+            Some(self.apply_plan(&plan, options.bulk).await)
+        };
+        Ok(Reconciliation { plan, report })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::user::{UserDataLimitResetStrategy, Vless, Vmess};
+
+    fn current(username: &str, status: &str) -> UserResponse {
+        let user = serde_json::json!({
+            "proxies": {"vless": {"id": "35e4e39c-7d5c-4f4b-8b71-558e4f37ff53", "flow": ""}},
+            "expire": null,
+            "data_limit": 0,
+            "data_limit_reset_strategy": "no_reset",
+            "inbounds": {"vless": ["VLESS TCP REALITY", "VLESS WS"]},
+            "note": "kept",
+            "sub_updated_at": null,
+            "sub_last_user_agent": null,
+            "online_at": null,
+            "on_hold_expire_duration": null,
+            "on_hold_timeout": null,
+            "auto_delete_in_days": null,
+            "username": username,
+            "status": status,
+            "used_traffic": 0,
+            "lifetime_used_traffic": 0,
+            "created_at": "2024-01-01T00:00:00",
+            "links": [],
+            "subscription_url": "",
+            "excluded_inbounds": {},
+            "admin": {"username": "admin", "is_sudo": true, "telegram_id": null, "discord_webhook": null}
+        });
+        serde_json::from_str(&user.to_string()).unwrap()
+    }
+
+    #[test]
+    fn plans_changes() {
+        let current = [
+            current("alice", "active"),
+            current("bob", "limited"),
+            current("carol", "active"),
+        ];
+        let inbounds = Inbounds {
+            vless: Some(vec![
+                "VLESS WS".to_string(),
+                "VLESS TCP REALITY".to_string(),
+            ]),
+            ..Default::default()
+        };
+        let alice = UserCreate::builder("alice")
+            .vless(Vless::default())
+            .inbounds(inbounds)
+            .build()
+            .unwrap();
+        let bob = UserCreate::builder("bob")
+            .vmess(Vmess::default())
+            .data_limit(1 << 30)
+            .data_limit_reset_strategy(UserDataLimitResetStrategy::Month)
+            .build()
+            .unwrap();
+        let dave = UserCreate::builder("dave")
+            .vless(Vless::default())
+            .build()
+            .unwrap();
+        let desired = vec![alice.into(), bob.into(), DesiredUser::from(dave)];
+
+        let plan = Plan::new(desired.clone(), &current, false);
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.create[0].user.username, "dave");
+        assert!(plan.delete.is_empty());
+        let modify = &plan.modify[0];
+        assert_eq!(modify.username, "bob");
+        assert_eq!(
+            modify.changes,
+            ["data_limit", "data_limit_reset_strategy", "proxies"]
+        );
+        assert_eq!(modify.modify.proxies.as_ref().unwrap().vless, None);
+
+        let plan = Plan::new(desired, &current, true);
+        assert_eq!(plan.delete, ["carol"]);
+        assert_eq!(
+            plan.to_string(),
+            "+ dave\n~ bob: data_limit, data_limit_reset_strategy, proxies\n- carol\n1 unchanged\n"
+        );
+    }
+
+    #[test]
+    fn keeps_credentials_and_toggles_disabled() {
+        let current = [current("alice", "disabled"), current("bob", "active")];
+        let user = |username| {
+            UserCreate::builder(username)
+                .vless(Vless {
+                    flow: Some("xtls-rprx-vision".to_string()),
+                    ..Default::default()
+                })
+                .build()
+                .unwrap()
+        };
+        let bob = DesiredUser {
+            user: user("bob"),
+            disabled: true,
+        };
+        let plan = Plan::new(vec![user("alice").into(), bob], &current, false);
+
+        let alice = &plan.modify[0];
+        assert_eq!(alice.changes, ["proxies", "status"]);
+        assert_eq!(alice.modify.status, Some(UserStatusModify::Active));
+        let vless = alice
+            .modify
+            .proxies
+            .as_ref()
+            .unwrap()
+            .vless
+            .as_ref()
+            .unwrap();
+        assert_eq!(
+            vless.id.as_deref(),
+            Some("35e4e39c-7d5c-4f4b-8b71-558e4f37ff53")
+        );
+        assert_eq!(vless.flow.as_deref(), Some("xtls-rprx-vision"));
+        assert_eq!(
+            plan.modify[1].modify.status,
+            Some(UserStatusModify::Disabled)
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! # Retry module
+//!
+//! This module contains the retry policy used by the Marzban API client to retry failed requests.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{header::RETRY_AFTER, Method, Response, StatusCode};
+
+/// Controls if and how failed requests are retried.
+///
+/// Requests using safe methods (`GET`, `HEAD`, `OPTIONS`) are retried automatically. Mutations such as
+/// [`MarzbanAPIClient::add_user()`](crate::client::MarzbanAPIClient::add_user) are only retried when
+/// [`RetryPolicy::retry_mutations`] is enabled, as a request which reached the server before the
+/// connection dropped would be applied twice.
+///
+/// The client does not retry by default, see [`RetryPolicy::none()`]. [`RetryPolicy::default()`]
+/// provides a reasonable configuration for flaky links.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use marzban_api::{client::MarzbanAPIClient, retry::RetryPolicy};
+///
+/// let client = MarzbanAPIClient::builder("https://panel.example.com")
+///     .retry_policy(RetryPolicy {
+///         max_attempts: 5,
+///         initial_backoff: Duration::from_millis(500),
+///         ..Default::default()
+///     })
+///     .build()
+///     .expect("Failed to build client");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between two attempts.
+    pub max_backoff: Duration,
+    /// Factor the delay is multiplied with after each attempt.
+    pub multiplier: f64,
+    /// Randomize each delay between half and the full computed delay, to avoid many clients
+    /// retrying in lockstep.
+    pub jitter: bool,
+    /// Response status codes which are retried.
+    pub retry_statuses: Vec<StatusCode>,
+    /// Retry on connection errors and timeouts.
+    pub retry_network_errors: bool,
+    /// Wait for the duration of the `Retry-After` header when present instead of the computed
+    /// delay. If it exceeds [`RetryPolicy::max_backoff`], the response is returned without retrying.
+    pub respect_retry_after: bool,
+    /// Also retry non-idempotent requests (`POST`, `PUT`, `DELETE`, ...).
+    pub retry_mutations: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: true,
+            retry_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_network_errors: true,
+            respect_retry_after: true,
+            retry_mutations: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which never retries. This is the default of the client.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Whether requests with the given method may be retried.
+    pub(crate) fn allows(&self, method: &Method) -> bool {
+        self.max_attempts > 1
+            && (self.retry_mutations
+                || matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS))
+    }
+
+    /// Whether the given error is worth another attempt.
+    pub(crate) fn is_retryable_error(&self, error: &reqwest::Error) -> bool {
+        self.retry_network_errors
+            && (error.is_connect() || error.is_timeout() || error.is_request())
+    }
+
+    /// The delay before retrying the given response, or [`None`] if it should not be retried.
+    pub(crate) fn response_delay(&self, response: &Response, attempt: u32) -> Option<Duration> {
+        if !self.retry_statuses.contains(&response.status()) {
+            return None;
+        }
+        if self.respect_retry_after {
+            if let Some(delay) = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_retry_after(value, Utc::now()))
+            {
+                return (delay <= self.max_backoff).then_some(delay);
+            }
+        }
+        Some(self.backoff(attempt))
+    }
+
+    /// The delay after the given (1-based) failed attempt.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self
+            .initial_backoff
+            .mul_f64(self.multiplier.max(1.0).powi(exponent).min(u32::MAX as f64))
+            .min(self.max_backoff);
+        if self.jitter {
+            delay / 2 + delay.mul_f64(fastrand::f64() / 2.0)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Parse a `Retry-After` header value, either in delay-seconds or HTTP-date form.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_exponentially_and_is_capped() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(350));
+        assert_eq!(policy.backoff(100), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            let delay = policy.backoff(1);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn only_safe_methods_are_retried_by_default() {
+        let policy = RetryPolicy::default();
+        assert!(policy.allows(&Method::GET));
+        assert!(!policy.allows(&Method::POST));
+        assert!(!RetryPolicy::none().allows(&Method::GET));
+        let policy = RetryPolicy {
+            retry_mutations: true,
+            ..Default::default()
+        };
+        assert!(policy.allows(&Method::POST));
+    }
+}
@@ -0,0 +1,1165 @@
+#![cfg(feature = "mock")]
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{TimeZone, Utc};
+use futures::{StreamExt, TryStreamExt};
+use marzban_api::{
+    api::{
+        subscription::{ClientTypes, SubscriptionUserAgent},
+        user::GetUsersQueryParams,
+    },
+    backup::{self, ConflictStrategy, ExportFormat, ImportOptions},
+    bulk::{BulkOptions, BulkOutcome},
+    client::{Credentials, MarzbanAPIClient},
+    error::ApiError,
+    links::ShareLink,
+    logs::LogsStreamOptions,
+    migrate::{migrate_users, MigrationOptions},
+    mock::{MockServer, MOCK_ADMIN_USERNAME},
+    models::{
+        admin::{AdminCreate, AdminModify},
+        node::{NodeCreate, NodeModify},
+        proxy::{ProxyInboundPort, ProxyTypes},
+        subscription::Subscription,
+        units::{ByteSize, Expiry},
+        user::{
+            Inbounds, Shadowsocks, Trojan, UserCreate, UserDataLimitResetStrategy, UserModify,
+            UserStatus, UserStatusModify, Vless, Vmess,
+        },
+        user_template::{UserTemplateCreate, UserTemplateModify},
+    },
+    reconcile::{DesiredUser, ReconcileOptions},
+    retry::RetryPolicy,
+};
+use reqwest::StatusCode;
+
+fn user(username: &str) -> UserCreate {
+    UserCreate::builder(username)
+        .vless(Vless::default())
+        .build()
+        .unwrap()
+}
+
+fn template(name: &str) -> UserTemplateCreate {
+    UserTemplateCreate {
+        name: Some(name.to_string()),
+        data_limit: ByteSize::kib(1),
+        expire_duration: 3600,
+        username_prefix: "pre_".to_string(),
+        username_suffix: "_suf".to_string(),
+        inbounds: HashMap::from([("vless".to_string(), vec!["VLESS TCP REALITY".to_string()])]),
+    }
+}
+
+fn node(name: &str) -> NodeCreate {
+    NodeCreate {
+        name: name.to_string(),
+        address: "10.0.0.1".to_string(),
+        port: 62050,
+        api_port: 62051,
+        usage_coefficient: 1.0,
+        add_as_new_host: false,
+    }
+}
+
+#[tokio::test]
+async fn base_url_and_authentication() {
+    let server = MockServer::start().await;
+    let client = server.client();
+
+    assert!(client.base_url().await.unwrap().contains("Marzban"));
+    assert!(matches!(
+        client.get_current_admin().await,
+        Err(ApiError::Unauthorized(_))
+    ));
+    assert!(matches!(
+        client
+            .authenticate(MockServer::admin_auth(MOCK_ADMIN_USERNAME, "wrong"))
+            .await,
+        Err(ApiError::Unauthorized(_))
+    ));
+
+    let token = client
+        .admin_token(MockServer::admin_auth("admin", "admin"))
+        .await
+        .unwrap();
+    assert_eq!(token.token_type.as_deref(), Some("bearer"));
+
+    let client = server.authenticated_client().await;
+    let admin = client.get_current_admin().await.unwrap();
+    assert_eq!(admin.username, MOCK_ADMIN_USERNAME);
+    assert!(admin.is_sudo);
+}
+
+#[tokio::test]
+async fn admins() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+
+    let created = client
+        .create_admin(AdminCreate {
+            username: "operator".to_string(),
+            is_sudo: false,
+            telegram_id: Some(42),
+            discord_webhook: None,
+            password: "secret".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(created.telegram_id, Some(42));
+
+    let duplicate = client
+        .create_admin(AdminCreate {
+            username: "operator".to_string(),
+            is_sudo: false,
+            telegram_id: None,
+            discord_webhook: None,
+            password: "secret".to_string(),
+        })
+        .await;
+    assert!(matches!(duplicate, Err(ApiError::Conflict(_))));
+
+    let modified = client
+        .modify_admin(
+            "operator",
+            AdminModify {
+                password: None,
+                is_sudo: true,
+                telegram_id: None,
+                discord_webhook: Some("https://discord.example.com".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(modified.is_sudo);
+
+    let admins = client.get_admins(None, None, None::<String>).await.unwrap();
+    assert_eq!(admins.len(), 2);
+    let filtered = client
+        .get_admins(None, Some(1), Some("oper"))
+        .await
+        .unwrap();
+    assert_eq!(filtered[0].username, "operator");
+
+    client.delete_admin("operator").await.unwrap();
+    assert!(matches!(
+        client.delete_admin("operator").await,
+        Err(ApiError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn non_sudo_admins_are_forbidden_and_scoped() {
+    let server = MockServer::start().await;
+    server.add_admin("reseller", "reseller", false);
+    let sudo = server.authenticated_client().await;
+    let reseller = server.client();
+    reseller
+        .authenticate(MockServer::admin_auth("reseller", "reseller"))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        reseller.get_nodes().await,
+        Err(ApiError::Forbidden(_))
+    ));
+    assert!(matches!(
+        reseller.get_core_config().await,
+        Err(ApiError::Forbidden(_))
+    ));
+
+    sudo.add_user(user("sudo_user")).await.unwrap();
+    reseller.add_user(user("reseller_user")).await.unwrap();
+
+    let visible = reseller
+        .get_users(GetUsersQueryParams::default())
+        .await
+        .unwrap();
+    assert_eq!(visible.total, 1);
+    assert_eq!(visible.users[0].admin.username, "reseller");
+    assert!(matches!(
+        reseller.get_user("sudo_user").await,
+        Err(ApiError::Forbidden(_))
+    ));
+}
+
+#[tokio::test]
+async fn users() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+
+    let created = client
+        .add_user(
+            UserCreate::builder("alice")
+                .vmess(Vmess::default())
+                .trojan(Trojan::default())
+                .data_limit(1 << 30)
+                .note("first")
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(created.status, UserStatus::Active);
+    let links: Vec<ShareLink> = created.share_links().collect::<Result<_, _>>().unwrap();
+    let trojan = links
+        .iter()
+        .find(|link| link.protocol() == ProxyTypes::Trojan)
+        .unwrap();
+    assert_eq!(trojan.remark(), "Trojan Websocket TLS (alice)");
+    assert!(created.proxies.vmess.unwrap().id.is_some());
+    assert!(created.proxies.trojan.unwrap().password.is_some());
+    assert_eq!(
+        created.inbounds.trojan,
+        Some(vec!["Trojan Websocket TLS".to_string()])
+    );
+    assert_eq!(created.links.len(), 2);
+    assert!(created.subscription_url.starts_with("/sub/"));
+
+    assert!(matches!(
+        client.add_user(user("alice")).await,
+        Err(ApiError::Conflict(_))
+    ));
+    assert_eq!(
+        client.get_user("alice").await.unwrap().note.as_deref(),
+        Some("first")
+    );
+    assert!(matches!(
+        client.get_user("nobody").await,
+        Err(ApiError::NotFound(_))
+    ));
+
+    let modified = client
+        .modify_user(
+            "alice",
+            UserModify::builder()
+                .note("second")
+                .status(UserStatusModify::Disabled)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(modified.note.as_deref(), Some("second"));
+    assert_eq!(modified.status, UserStatus::Disabled);
+    assert_eq!(modified.data_limit, ByteSize::gib(1));
+
+    let restricted = client
+        .modify_user(
+            "alice",
+            UserModify::builder()
+                .inbounds(Inbounds {
+                    vmess: Some(vec!["VMess TCP".to_string()]),
+                    trojan: Some(vec!["Trojan Websocket TLS".to_string()]),
+                    ..Default::default()
+                })
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(restricted.excluded_inbounds.vmess, Some(vec![]));
+
+    server.set_used_traffic("alice", 4096);
+    assert_eq!(
+        client
+            .get_user_usage("alice", None::<String>, None::<String>)
+            .await
+            .unwrap()
+            .usages[0]
+            .used_traffic,
+        ByteSize::kib(4)
+    );
+    assert_eq!(
+        client
+            .reset_user_data_usage("alice")
+            .await
+            .unwrap()
+            .used_traffic,
+        ByteSize::ZERO
+    );
+
+    let old_url = created.subscription_url;
+    let revoked = client.revoke_user_subscription("alice").await.unwrap();
+    assert_ne!(revoked.subscription_url, old_url);
+
+    client.delete_user("alice").await.unwrap();
+    assert!(matches!(
+        client.delete_user("alice").await,
+        Err(ApiError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn user_validation() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+
+    let mut invalid = user("valid_name");
+    invalid.username = "Not Valid!".to_string();
+    assert!(matches!(
+        client.add_user(invalid).await,
+        Err(ApiError::ClientValidation(_))
+    ));
+
+    let no_proxies = UserCreate::builder("no_proxies").build().unwrap();
+    match client.add_user(no_proxies).await {
+        Err(ApiError::Validation(error)) => {
+            assert!(error.field_errors().contains_key("proxies"));
+        }
+        other => panic!("Expected a validation error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn users_listing_and_usage() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    server.add_admin("reseller", "reseller", false);
+
+    for name in ["carol", "alice", "bob", "dave"] {
+        client.add_user(user(name)).await.unwrap();
+        server.set_used_traffic(name, name.len() as u64);
+    }
+    client.set_owner_of_user("dave", "reseller").await.unwrap();
+    assert!(matches!(
+        client.set_owner_of_user("dave", "nobody").await,
+        Err(ApiError::NotFound(_))
+    ));
+
+    let page = client
+        .get_users(GetUsersQueryParams {
+            offset: Some(1),
+            limit: Some(2),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(page.total, 4);
+    let names: Vec<_> = page.users.iter().map(|u| u.username.as_str()).collect();
+    assert_eq!(names, ["bob", "carol"]);
+
+    let filtered = client
+        .get_users(GetUsersQueryParams {
+            username: Some(vec!["alice".to_string(), "bob".to_string()]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(filtered.total, 2);
+
+    let owned = client
+        .get_users(GetUsersQueryParams {
+            admin: Some(vec!["reseller".to_string()]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(owned.users[0].username, "dave");
+
+    let streamed: Vec<_> = client
+        .users_stream(GetUsersQueryParams::default())
+        .map_ok(|user| user.username)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(streamed, ["alice", "bob", "carol", "dave"]);
+
+    let usage = client
+        .get_all_users_usage(None::<String>, None::<String>, None::<Vec<String>>)
+        .await
+        .unwrap();
+    assert_eq!(usage.usages[0].used_traffic, ByteSize::b(5 + 5 + 3 + 4));
+    let usage = client
+        .get_all_users_usage(None::<String>, None::<String>, Some(vec!["reseller"]))
+        .await
+        .unwrap();
+    assert_eq!(usage.usages[0].used_traffic, ByteSize::b(4));
+
+    client.reset_all_users_data_usage().await.unwrap();
+    assert_eq!(
+        client.get_user("dave").await.unwrap().used_traffic,
+        ByteSize::ZERO
+    );
+}
+
+#[tokio::test]
+async fn expired_users() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+
+    let expired_at = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    client
+        .add_user(
+            UserCreate::builder("expired")
+                .vless(Vless::default())
+                .expire(expired_at)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    client.add_user(user("forever")).await.unwrap();
+
+    let before = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+    assert_eq!(
+        client.get_expired_users(Some(before), None).await.unwrap(),
+        ["expired"]
+    );
+    assert!(client
+        .get_expired_users(Some(before), Some(before))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        client.delete_expired_users(None, None).await.unwrap(),
+        ["expired"]
+    );
+    assert!(matches!(
+        client.delete_expired_users(None, None).await,
+        Err(ApiError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn bulk_users() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    client.add_user(user("user03")).await.unwrap();
+
+    let report = client
+        .bulk_add_users((0..10).map(|i| user(&format!("user{i:02}"))))
+        .await;
+    assert_eq!(report.results.len(), 10);
+    assert_eq!(report.succeeded().count(), 9);
+    assert!(matches!(
+        report.get("user03"),
+        Some(BulkOutcome::Failed(ApiError::Conflict(_)))
+    ));
+
+    let report = client
+        .bulk_modify_users_with_options(
+            ["user00", "nobody", "user01"].map(|username| {
+                (
+                    username.to_string(),
+                    UserModify::builder().note("bulk").build().unwrap(),
+                )
+            }),
+            BulkOptions {
+                concurrency: 1,
+                stop_on_error: true,
+            },
+        )
+        .await;
+    assert_eq!(report.succeeded().count(), 1);
+    assert!(matches!(
+        report.get("nobody"),
+        Some(BulkOutcome::Failed(ApiError::NotFound(_)))
+    ));
+    assert_eq!(report.skipped().collect::<Vec<_>>(), ["user01"]);
+
+    let report = client
+        .bulk_delete_users((0..10).map(|i| format!("user{i:02}")))
+        .await;
+    assert!(report.is_success());
+    assert_eq!(
+        client
+            .get_users(GetUsersQueryParams::default())
+            .await
+            .unwrap()
+            .total,
+        0
+    );
+}
+
+#[tokio::test]
+async fn reconcile_users() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    client.add_user(user("alice")).await.unwrap();
+    client.add_user(user("stale")).await.unwrap();
+    client.add_user(user("carol")).await.unwrap();
+    let alice = client.get_user("alice").await.unwrap();
+
+    let desired = vec![
+        DesiredUser::from(
+            UserCreate::builder("alice")
+                .vless(Vless::default())
+                .data_limit(1 << 30)
+                .build()
+                .unwrap(),
+        ),
+        DesiredUser {
+            user: user("bob"),
+            disabled: true,
+        },
+        // Without proxies, the current ones are kept.
+        DesiredUser::from(UserCreate::builder("carol").note("vip").build().unwrap()),
+    ];
+    let options = ReconcileOptions {
+        prune: true,
+        dry_run: true,
+        ..Default::default()
+    };
+    let reconciliation = client.reconcile(desired.clone(), options).await.unwrap();
+    assert!(reconciliation.report.is_none());
+    assert_eq!(
+        reconciliation.plan.to_string(),
+        "+ bob\n~ alice: data_limit\n~ carol: note\n- stale\n0 unchanged\n"
+    );
+    assert!(client.get_user("bob").await.is_err());
+
+    let options = ReconcileOptions {
+        prune: true,
+        ..Default::default()
+    };
+    let report = client
+        .reconcile(desired.clone(), options)
+        .await
+        .unwrap()
+        .report
+        .unwrap();
+    assert!(report.is_success());
+    let updated = client.get_user("alice").await.unwrap();
+    assert_eq!(updated.data_limit, ByteSize::gib(1));
+    assert_eq!(updated.proxies, alice.proxies);
+    assert_eq!(
+        client.get_user("bob").await.unwrap().status,
+        UserStatus::Disabled
+    );
+    assert!(client.get_user("stale").await.is_err());
+    assert!(client
+        .get_user("carol")
+        .await
+        .unwrap()
+        .proxies
+        .vless
+        .is_some());
+
+    // Applying the plan converges, so planning again has no changes.
+    let plan = client.plan_reconciliation(desired, true).await.unwrap();
+    assert!(plan.is_empty(), "{plan}");
+    assert_eq!(plan.unchanged, 3);
+}
+
+#[tokio::test]
+async fn export_import_users() {
+    let source = MockServer::start().await;
+    let source_client = source.authenticated_client().await;
+    let target = MockServer::start().await;
+    let target_client = target.authenticated_client().await;
+    for username in ["alice", "bob", "carol"] {
+        source_client.add_user(user(username)).await.unwrap();
+    }
+    target_client.add_user(user("bob")).await.unwrap();
+    source_client
+        .modify_user(
+            "carol",
+            UserModify::builder()
+                .status(UserStatusModify::Disabled)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let exported = source_client.export_users().await.unwrap();
+    let mut buffer = Vec::new();
+    backup::write_users(&mut buffer, &exported, ExportFormat::Csv).unwrap();
+    let users = backup::read_users(buffer.as_slice(), ExportFormat::Csv).unwrap();
+    assert_eq!(users, exported);
+
+    let report = target_client
+        .import_users(users.clone(), ImportOptions::default())
+        .await
+        .unwrap();
+    assert!(report.imported.is_success());
+    assert_eq!(report.skipped, ["bob"]);
+    let alice = target_client.get_user("alice").await.unwrap();
+    assert_eq!(
+        alice.proxies,
+        source_client.get_user("alice").await.unwrap().proxies
+    );
+    assert_eq!(
+        target_client.get_user("carol").await.unwrap().status,
+        UserStatus::Disabled
+    );
+
+    let options = ImportOptions {
+        conflict: ConflictStrategy::Rename,
+        ..Default::default()
+    };
+    let report = target_client
+        .import_users(users.clone(), options)
+        .await
+        .unwrap();
+    assert_eq!(report.renamed.len(), 3);
+    assert!(report
+        .renamed
+        .contains(&("bob".to_string(), "bob_1".to_string())));
+    let renamed = target_client.get_user("alice_1").await.unwrap();
+    assert_ne!(renamed.proxies, alice.proxies);
+
+    let options = ImportOptions {
+        conflict: ConflictStrategy::Overwrite,
+        ..Default::default()
+    };
+    let report = target_client.import_users(users, options).await.unwrap();
+    assert_eq!(report.imported.results.len(), 3);
+    assert!(report.imported.is_success());
+}
+
+#[tokio::test]
+async fn migrate_users_between_panels() {
+    let source = MockServer::start().await;
+    let source_client = source.authenticated_client().await;
+    let target = MockServer::start().await;
+    let target_client = target.authenticated_client().await;
+    source.add_admin("reseller", "secret", false);
+    source.add_admin("other", "secret", false);
+    target.add_admin("reseller", "secret", false);
+
+    let alice = UserCreate::builder("alice")
+        .vless(Vless::default())
+        .trojan(Trojan::default())
+        .data_limit(1000)
+        .expire(1_900_000_000)
+        .note("moved")
+        .build()
+        .unwrap();
+    let alice = source_client.add_user(alice).await.unwrap();
+    source.set_used_traffic("alice", 400);
+    source_client
+        .set_owner_of_user("alice", "reseller")
+        .await
+        .unwrap();
+    source_client.add_user(user("bob")).await.unwrap();
+    source_client
+        .set_owner_of_user("bob", "other")
+        .await
+        .unwrap();
+    source_client.add_user(user("carol")).await.unwrap();
+    source_client
+        .modify_user(
+            "carol",
+            UserModify::builder()
+                .status(UserStatusModify::Disabled)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    source_client.add_user(user("dave")).await.unwrap();
+    target_client.add_user(user("dave")).await.unwrap();
+    let erin = UserCreate::builder("erin")
+        .vless(Vless::default())
+        .data_limit(1000)
+        .data_limit_reset_strategy(UserDataLimitResetStrategy::Month)
+        .build()
+        .unwrap();
+    source_client.add_user(erin).await.unwrap();
+    source.set_used_traffic("erin", 400);
+
+    let mut options = MigrationOptions::default();
+    options.inbound_map.insert(
+        "Trojan Websocket TLS".to_string(),
+        "Trojan gRPC".to_string(),
+    );
+    let report = migrate_users(&source_client, &target_client, options)
+        .await
+        .unwrap();
+    assert!(report.is_success());
+    assert_eq!(report.skipped, ["dave"]);
+
+    let migrated = target_client.get_user("alice").await.unwrap();
+    assert_eq!(migrated.proxies, alice.proxies);
+    assert_eq!(migrated.data_limit, ByteSize::b(600));
+    assert_eq!(migrated.expire, Expiry::from_timestamp(1_900_000_000));
+    assert_eq!(migrated.note.as_deref(), Some("moved"));
+    assert_eq!(migrated.admin.username, "reseller");
+    assert_eq!(
+        migrated.inbounds.trojan,
+        Some(vec!["Trojan Websocket TLS".to_string()])
+    );
+    assert_eq!(
+        target_client.get_user("carol").await.unwrap().status,
+        UserStatus::Disabled
+    );
+    let resetting = target_client.get_user("erin").await.unwrap();
+    assert_eq!(resetting.data_limit, ByteSize::b(1000));
+    assert_eq!(
+        resetting.data_limit_reset_strategy,
+        UserDataLimitResetStrategy::Month
+    );
+    let issues: Vec<_> = report
+        .issues()
+        .map(|(username, issue)| format!("{username}: {issue}"))
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            "alice: inbounds dropped: Trojan gRPC",
+            "bob: owner other does not exist on the target",
+            "erin: used traffic of 400 B not copied, as the data limit resets"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn user_templates() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+
+    let created = client.add_user_template(template("basic")).await.unwrap();
+    assert_eq!(created.id, 1);
+    assert!(matches!(
+        client.add_user_template(template("basic")).await,
+        Err(ApiError::Conflict(_))
+    ));
+    match client.add_user_template(template(&"x".repeat(65))).await {
+        Err(ApiError::Validation(error)) => {
+            assert!(error.field_errors().contains_key("name"));
+        }
+        other => panic!("Expected a validation error, got {other:?}"),
+    }
+
+    assert_eq!(
+        client.get_user_template(1).await.unwrap().name.as_deref(),
+        Some("basic")
+    );
+    let modified = client
+        .modify_user_template(
+            1,
+            UserTemplateModify {
+                name: Some("premium".to_string()),
+                data_limit: ByteSize::kib(2),
+                expire_duration: 0,
+                username_prefix: "p_".to_string(),
+                username_suffix: "_s".to_string(),
+                inbounds: HashMap::new(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(modified.data_limit, ByteSize::kib(2));
+    assert_eq!(
+        client.get_user_templates(None, None).await.unwrap().len(),
+        1
+    );
+
+    client.remove_user_template(1).await.unwrap();
+    assert!(matches!(
+        client.get_user_template(1).await,
+        Err(ApiError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn nodes() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+
+    assert!(client
+        .get_node_settings()
+        .await
+        .unwrap()
+        .certificate
+        .contains("CERTIFICATE"));
+
+    let created = client.add_node(node("edge")).await.unwrap();
+    assert!(matches!(
+        client.add_node(node("edge")).await,
+        Err(ApiError::Conflict(_))
+    ));
+    let id = created.id as i32;
+    assert_eq!(client.get_node(id).await.unwrap().name, "edge");
+
+    let modified = client
+        .modify_node(
+            id,
+            NodeModify {
+                name: None,
+                address: Some("10.0.0.2".to_string()),
+                port: None,
+                api_port: None,
+                usage_coefficient: Some(2.0),
+                status: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(modified.address, "10.0.0.2");
+    assert_eq!(modified.usage_coefficient, 2.0);
+
+    client.reconnect_node(id).await.unwrap();
+    assert_eq!(client.get_nodes().await.unwrap().len(), 1);
+    let usage = client
+        .get_nodes_usage(None::<String>, None::<String>)
+        .await
+        .unwrap();
+    assert_eq!(usage.usages.len(), 2);
+
+    client.remove_node(id).await.unwrap();
+    assert!(matches!(
+        client.get_node(id).await,
+        Err(ApiError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn system_and_core() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    client.add_user(user("alice")).await.unwrap();
+
+    let stats = client.get_system_stats().await.unwrap();
+    assert_eq!(stats.total_user, 1);
+    assert_eq!(stats.users_active, 1);
+
+    let inbounds = client.get_inbounds().await.unwrap();
+    assert_eq!(inbounds[&ProxyTypes::Vless][0].tag, "VLESS TCP REALITY");
+
+    let mut hosts = client.get_hosts().await.unwrap();
+    assert_eq!(hosts.len(), 4);
+    let vless = hosts.remove("VLESS TCP REALITY").unwrap();
+    let modified = client
+        .modify_hosts(HashMap::from([("VLESS TCP REALITY".to_string(), vless)]))
+        .await
+        .unwrap();
+    assert_eq!(modified.len(), 4);
+
+    assert!(client.get_core_stats().await.unwrap().started);
+    client.restart_core().await.unwrap();
+
+    let config = client.get_core_config().await.unwrap();
+    assert!(config.contains("VLESS TCP REALITY"));
+    let modified = client
+        .modify_core_config(config.replace("warning", "error"))
+        .await
+        .unwrap();
+    assert!(modified.contains("\"loglevel\":\"error\""));
+    assert!(matches!(
+        client.modify_core_config("[]").await,
+        Err(ApiError::Serialization(_))
+    ));
+
+    let mut xray = client.get_xray_config().await.unwrap();
+    assert_eq!(xray.inbounds.len(), 5);
+    xray.inbound_mut("VMess TCP").unwrap().port = Some(ProxyInboundPort::Integer(8081));
+    let modified = client.modify_xray_config(&xray).await.unwrap();
+    assert_eq!(modified, xray);
+    assert_eq!(client.get_xray_config().await.unwrap(), xray);
+}
+
+#[tokio::test]
+async fn core_config_dry_run() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    let current = client.get_xray_config().await.unwrap();
+    assert!(client.diff_xray_config(&current).await.unwrap().is_empty());
+
+    let mut proposed = current.clone();
+    proposed.outbounds.pop();
+    let requests = server.request_count();
+    assert!(matches!(
+        client.apply_xray_config(&proposed, true).await,
+        Err(ApiError::ClientValidation(_))
+    ));
+    assert_eq!(server.request_count(), requests);
+
+    proposed.routing = None;
+    let diff = client.apply_xray_config(&proposed, true).await.unwrap();
+    assert_eq!(diff.outbounds[0].tag(), "BLOCK");
+    assert_eq!(diff.rules.len(), 3);
+    assert_eq!(client.get_xray_config().await.unwrap(), current);
+
+    let applied = client.apply_xray_config(&proposed, false).await.unwrap();
+    assert_eq!(applied, diff);
+    assert_eq!(client.get_xray_config().await.unwrap(), proposed);
+}
+
+#[tokio::test]
+async fn subscriptions() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    let alice = client
+        .add_user(
+            UserCreate::builder("alice")
+                .vless(Vless::default())
+                .shadowsocks(Shadowsocks::default())
+                .data_limit(1 << 30)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let token = server.subscription_token("alice").unwrap();
+    let subscription_token = alice.subscription_token().unwrap();
+    assert_eq!(subscription_token.as_str(), token);
+    let payload = subscription_token.payload().unwrap();
+    assert_eq!(payload.username, "alice");
+    assert!((Utc::now() - payload.created_at).num_seconds() < 60);
+    let url = client
+        .user_subscription_url(&alice, Some(ClientTypes::Clash))
+        .unwrap();
+    assert_eq!(url, format!("{}/sub/{token}/clash", server.url()));
+    let clash = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    assert!(clash.starts_with("proxies:"));
+
+    // Subscriptions are public, an unauthenticated client is enough.
+    let public = server.client();
+    let response = public.user_subscription(&token).await.unwrap();
+    assert!(!response.body.is_empty());
+    let userinfo = response.userinfo.unwrap();
+    assert_eq!(userinfo.total, Some(1 << 30));
+    assert_eq!(userinfo.remaining(), Some(1 << 30));
+    assert_eq!(userinfo.expire, None);
+    assert_eq!(
+        response.update_interval,
+        Some(Duration::from_secs(12 * 3600))
+    );
+    assert_eq!(response.title.as_deref(), Some("alice"));
+    assert_eq!(
+        response.content_disposition.as_deref(),
+        Some("attachment; filename=\"alice\"")
+    );
+    assert_eq!(
+        public
+            .user_subscription_info(&token)
+            .await
+            .unwrap()
+            .username,
+        "alice"
+    );
+    assert_eq!(
+        public
+            .user_get_usage(&token, None::<String>, None::<String>)
+            .await
+            .unwrap()
+            .username,
+        "alice"
+    );
+
+    for (user_agent, client_type) in [
+        (SubscriptionUserAgent::Clash, ClientTypes::Clash),
+        (SubscriptionUserAgent::ClashMeta, ClientTypes::ClashMeta),
+        (SubscriptionUserAgent::SingBox, ClientTypes::SingBox),
+        (SubscriptionUserAgent::Hiddify, ClientTypes::SingBox),
+        (SubscriptionUserAgent::V2rayNG, ClientTypes::V2Ray),
+    ] {
+        let response = public
+            .user_subscription_with_user_agent(&token, user_agent)
+            .await
+            .unwrap();
+        assert!(response.decode(client_type).is_ok());
+    }
+    let page = public
+        .user_subscription_with_user_agent(&token, SubscriptionUserAgent::Browser)
+        .await
+        .unwrap();
+    assert_eq!(page.content_type.as_deref(), Some("text/html"));
+    assert!(matches!(
+        public
+            .user_subscription_with_user_agent(
+                &token,
+                SubscriptionUserAgent::Custom("invalid\n".to_string())
+            )
+            .await,
+        Err(ApiError::ClientValidation(_))
+    ));
+
+    for client_type in [
+        ClientTypes::SingBox,
+        ClientTypes::ClashMeta,
+        ClientTypes::Clash,
+        ClientTypes::Outline,
+        ClientTypes::V2Ray,
+        ClientTypes::V2RayJSON,
+    ] {
+        let response = public
+            .user_subscription_with_client_type(&token, client_type)
+            .await
+            .unwrap();
+        assert!(!response.body.is_empty());
+        assert!(response.userinfo.is_some());
+        let subscription = public
+            .user_subscription_decoded(&token, client_type)
+            .await
+            .unwrap();
+        assert_eq!(subscription.client_type(), client_type);
+    }
+
+    let Subscription::V2Ray(links) = public
+        .user_subscription_decoded(&token, ClientTypes::V2Ray)
+        .await
+        .unwrap()
+    else {
+        unreachable!()
+    };
+    assert_eq!(links.len(), 2);
+    let Subscription::Clash(clash) = public
+        .user_subscription_decoded(&token, ClientTypes::Clash)
+        .await
+        .unwrap()
+    else {
+        unreachable!()
+    };
+    let proxies: Vec<_> = clash
+        .proxies
+        .iter()
+        .map(|p| p.proxy_type.as_str())
+        .collect();
+    assert_eq!(proxies, ["vless", "ss"]);
+    let Subscription::Outline(outline) = public
+        .user_subscription_decoded(&token, ClientTypes::Outline)
+        .await
+        .unwrap()
+    else {
+        unreachable!()
+    };
+    assert_eq!(outline.method, "chacha20-ietf-poly1305");
+
+    assert!(matches!(
+        public.user_subscription("invalid").await,
+        Err(ApiError::NotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn reauthenticates_after_token_expiry() {
+    let server = MockServer::start().await;
+    let client = MarzbanAPIClient::builder(&server.url())
+        .credentials(Credentials::Static(MockServer::admin_auth(
+            "admin", "admin",
+        )))
+        .build()
+        .unwrap();
+
+    client.get_current_admin().await.unwrap();
+    server.expire_tokens();
+    client.get_current_admin().await.unwrap();
+
+    let without_credentials = server.authenticated_client().await;
+    server.expire_tokens();
+    assert!(matches!(
+        without_credentials.get_current_admin().await,
+        Err(ApiError::Unauthorized(_))
+    ));
+}
+
+#[tokio::test]
+async fn retries_idempotent_requests() {
+    let server = MockServer::start().await;
+    let client = MarzbanAPIClient::builder(&server.url())
+        .retry_policy(RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+    client
+        .authenticate(MockServer::admin_auth("admin", "admin"))
+        .await
+        .unwrap();
+
+    let before = server.request_count();
+    server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 2);
+    client.get_system_stats().await.unwrap();
+    assert_eq!(server.request_count() - before, 3);
+
+    // Mutations are not retried by default.
+    server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 1);
+    let error = client.add_user(user("alice")).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+}
+
+fn fast_reconnect() -> LogsStreamOptions {
+    LogsStreamOptions {
+        reconnect_delay: Duration::from_millis(10),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn core_logs() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    server.push_core_log("first");
+
+    let mut logs = Box::pin(client.core_logs_stream_with_options(fast_reconnect()));
+    assert_eq!(logs.next().await.unwrap().unwrap().message, "first");
+    server.push_core_log("second");
+    assert_eq!(logs.next().await.unwrap().unwrap().message, "second");
+
+    // The history is replayed after reconnecting.
+    server.disconnect_logs();
+    server.push_core_log("third");
+    let lines: Vec<_> = logs
+        .by_ref()
+        .take(3)
+        .map(|line| line.unwrap().message)
+        .collect()
+        .await;
+    assert_eq!(lines, ["first", "second", "third"]);
+}
+
+#[tokio::test]
+async fn core_logs_with_interval() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    server.push_core_log("first");
+    server.push_core_log("second");
+
+    let logs = client.core_logs_stream_with_options(LogsStreamOptions {
+        interval: Some(Duration::from_millis(50)),
+        ..Default::default()
+    });
+    let lines: Vec<_> = logs
+        .take(2)
+        .map(|line| line.unwrap().message)
+        .collect()
+        .await;
+    assert_eq!(lines, ["first", "second"]);
+
+    let invalid = client
+        .core_logs_stream_with_options(LogsStreamOptions {
+            interval: Some(Duration::from_secs(11)),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(invalid[..], [Err(ApiError::ClientValidation(_))]));
+}
+
+#[tokio::test]
+async fn logs_authentication() {
+    let server = MockServer::start().await;
+    server.push_core_log("line");
+
+    let unauthenticated = server.client().core_logs_stream().collect::<Vec<_>>().await;
+    assert!(matches!(unauthenticated[..], [Err(ApiError::Forbidden(_))]));
+
+    let client = MarzbanAPIClient::builder(&server.url())
+        .credentials(Credentials::Static(MockServer::admin_auth(
+            "admin", "admin",
+        )))
+        .build()
+        .unwrap();
+    let mut logs = Box::pin(client.core_logs_stream());
+    assert_eq!(logs.next().await.unwrap().unwrap().message, "line");
+}
+
+#[tokio::test]
+async fn node_logs() {
+    let server = MockServer::start().await;
+    let client = server.authenticated_client().await;
+    let id = client.add_node(node("edge")).await.unwrap().id;
+    server.push_node_log(id.into(), "node line");
+
+    let mut logs = Box::pin(client.node_logs_stream(id as i32));
+    assert_eq!(logs.next().await.unwrap().unwrap().message, "node line");
+
+    let missing = client.node_logs_stream(404).collect::<Vec<_>>().await;
+    assert!(matches!(missing[..], [Err(ApiError::Forbidden(_))]));
+}